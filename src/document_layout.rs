@@ -2,11 +2,13 @@ use super::*;
 #[derive(Clone)]
 pub enum KanbanDocumentLayout {
     Queue(kanban::queue_view::QueueState),
-    Columnar([Vec<i32>; 3]),
+    /// The tasks in each of the document's columns
+    Columnar(Vec<Vec<i32>>),
     Search(kanban::search::SearchState),
     Focused(kanban::focused_layout::Focus),
     TreeOutline(kanban::tree_outline_layout::TreeOutline),
     NodeLayout(kanban::node_layout::NodeLayout),
+    Calendar(kanban::calendar_layout::Calendar),
 }
 impl PartialEq for KanbanDocumentLayout {
     fn eq(&self, other: &Self) -> bool {
@@ -21,26 +23,25 @@ impl PartialEq for KanbanDocumentLayout {
             KanbanDocumentLayout::NodeLayout(_) => {
                 matches!(other, KanbanDocumentLayout::NodeLayout(_))
             }
+            KanbanDocumentLayout::Calendar(_) => {
+                matches!(other, KanbanDocumentLayout::Calendar(_))
+            }
         }
     }
 }
 impl KanbanDocumentLayout {
     fn update_columnar(
-        columnar_cache: &mut [Vec<i32>; 3],
+        columnar_cache: &mut Vec<Vec<i32>>,
         document: &KanbanDocument,
         filter: &KanbanFilter,
     ) {
         columnar_cache.iter_mut().for_each(|x| x.clear());
+        columnar_cache.resize(document.columns().len(), Vec::new());
         for task in document.get_tasks() {
-            let index = match document.task_status(&task.id) {
-                kanban::Status::Ready => 0,
-                kanban::Status::Blocked => 1,
-                kanban::Status::Completed => 2,
-            };
             if !filter.matches(task, document) {
                 continue;
             }
-            columnar_cache[index].push(task.id);
+            columnar_cache[document.column_of(task)].push(task.id);
         }
     }
     pub fn inform_of_new_items(&mut self) {
@@ -57,23 +58,27 @@ impl KanbanDocumentLayout {
     ) {
         match self {
             KanbanDocumentLayout::Queue(x) => {
-                x.update(document);
+                x.update(document, filter, sort);
             }
             KanbanDocumentLayout::Columnar(array) => {
                 KanbanDocumentLayout::update_columnar(array, document, filter);
             }
             KanbanDocumentLayout::Search(search_state) => {
-                search_state.update(document);
+                search_state.force_update();
+                search_state.update(document, filter, sort);
             }
             KanbanDocumentLayout::Focused(focus) => {
-                focus.update(document);
+                focus.update(document, filter);
             }
             KanbanDocumentLayout::TreeOutline(tree) => {
-                tree.update(document, *sort, filter);
+                tree.update(document, sort, filter);
             }
             KanbanDocumentLayout::NodeLayout(nl) => {
                 nl.update(document, style, filter, sort);
             }
+            KanbanDocumentLayout::Calendar(calendar) => {
+                calendar.update(document, sort, filter);
+            }
         }
     }
 
@@ -86,13 +91,77 @@ impl KanbanDocumentLayout {
                 sort.sort_by(&mut focus.children, document);
                 sort.sort_by(&mut focus.ancestors, document);
             }
+            KanbanDocumentLayout::Calendar(calendar) => calendar.sort(document, sort),
+            // The others are sorted as they're updated
             _ => (),
         }
     }
+    /// The layout and its settings, for saving as a view.
+    pub fn preset(&self) -> LayoutPreset {
+        match self {
+            KanbanDocumentLayout::Queue(_) => LayoutPreset::Queue,
+            KanbanDocumentLayout::Columnar(_) => LayoutPreset::Columnar,
+            KanbanDocumentLayout::Search(search) => LayoutPreset::Search {
+                prompt: search.search_prompt.clone(),
+                sort_first: search.sort_first,
+            },
+            KanbanDocumentLayout::Focused(focus) => LayoutPreset::Focused(focus.cares_about),
+            KanbanDocumentLayout::TreeOutline(tree) => tree.preset(),
+            KanbanDocumentLayout::NodeLayout(nl) => nl.preset(),
+            KanbanDocumentLayout::Calendar(calendar) => LayoutPreset::Calendar {
+                week: matches!(calendar.span, CalendarSpan::Week),
+            },
+        }
+    }
+    /// The layout a view was saved with, leaving out any tasks that have since been deleted.
+    /// Its cache still needs updating.
+    pub fn from_preset(preset: &LayoutPreset, document: &KanbanDocument) -> Self {
+        match preset.without_missing(document) {
+            LayoutPreset::Columnar => KanbanDocumentLayout::default(),
+            LayoutPreset::Queue => KanbanDocumentLayout::Queue(QueueState::new()),
+            LayoutPreset::Search { prompt, sort_first } => {
+                let mut search = SearchState::new();
+                search.search_prompt = prompt;
+                search.sort_first = sort_first;
+                search.force_update();
+                KanbanDocumentLayout::Search(search)
+            }
+            LayoutPreset::Focused(focus) => {
+                KanbanDocumentLayout::Focused(kanban::focused_layout::Focus {
+                    cares_about: focus,
+                    children: Vec::new(),
+                    ancestors: Vec::new(),
+                })
+            }
+            LayoutPreset::TreeOutline {
+                focus,
+                exclude_completed,
+            } => KanbanDocumentLayout::TreeOutline(TreeOutline::from_preset(
+                focus,
+                exclude_completed,
+            )),
+            LayoutPreset::Node {
+                focus,
+                exclude_completed,
+                collapsed,
+            } => KanbanDocumentLayout::NodeLayout(NodeLayout::from_preset(
+                focus,
+                exclude_completed,
+                collapsed,
+            )),
+            LayoutPreset::Calendar { week } => {
+                let mut calendar = Calendar::new();
+                if week {
+                    calendar.span = CalendarSpan::Week;
+                }
+                KanbanDocumentLayout::Calendar(calendar)
+            }
+        }
+    }
 }
 impl Default for KanbanDocumentLayout {
     fn default() -> Self {
-        KanbanDocumentLayout::Columnar([Vec::new(), Vec::new(), Vec::new()])
+        KanbanDocumentLayout::Columnar(Vec::new())
     }
 }
 impl From<&KanbanDocumentLayout> for String {
@@ -104,6 +173,7 @@ impl From<&KanbanDocumentLayout> for String {
             KanbanDocumentLayout::Focused(_) => "Focus",
             KanbanDocumentLayout::TreeOutline(_) => "Tree outline",
             KanbanDocumentLayout::NodeLayout(_) => "Node outline",
+            KanbanDocumentLayout::Calendar(_) => "Calendar",
         }
         .into()
     }
@@ -117,33 +187,62 @@ impl From<&KanbanDocumentLayout> for String {
 impl KanbanRS {
     pub fn layout_columnar(&mut self, ui: &mut egui::Ui) {
         if let KanbanDocumentLayout::Columnar(cache) = &mut self.current_layout.clone() {
-            ui.columns(3, |columns| {
-                columns[0].label(RichText::new("Ready").heading());
-
-                self.document.read().layout_id_list(
-                    &mut columns[0],
-                    &cache[0],
-                    &mut self.hovered_task,
-                    &mut self.summary_actions_pending,
-                    "ReadyScrollArea",
-                );
-                columns[1].label(RichText::new("Blocked").heading());
-                self.document.read().layout_id_list(
-                    &mut columns[1],
-                    &cache[1],
-                    &mut self.hovered_task,
-                    &mut self.summary_actions_pending,
-                    "BlockedScrollArea",
-                );
-                columns[2].label(RichText::new("Completed").heading());
-
-                self.document.read().layout_id_list(
-                    &mut columns[2],
-                    &cache[2],
-                    &mut self.hovered_task,
-                    &mut self.summary_actions_pending,
-                    "CompletedScrollArea",
-                );
+            let document = self.document.read();
+            let names = document.columns();
+            // The workflow may have changed since the cache was last updated
+            if names.len() != cache.len() {
+                self.layout_cache_needs_updating = true;
+                return;
+            }
+            ui.columns(names.len(), |columns| {
+                for (index, (name, wip_limit)) in names.iter().enumerate() {
+                    let heading = match wip_limit {
+                        Some(limit) => {
+                            let text = RichText::new(format!(
+                                "{} ({}/{})",
+                                name,
+                                cache[index].len(),
+                                limit
+                            ))
+                            .heading();
+                            if cache[index].len() > *limit {
+                                text.color(columns[index].visuals().error_fg_color)
+                            } else {
+                                text
+                            }
+                        }
+                        None => RichText::new(*name).heading(),
+                    };
+                    let column = &mut columns[index];
+                    column.label(heading);
+                    document.layout_draggable_id_list(
+                        column,
+                        &cache[index],
+                        &mut self.hovered_task,
+                        &mut self.summary_actions_pending,
+                        format!("{}ScrollArea", name),
+                    );
+                    // Cards dropped onto another card are taken by it, so anything left
+                    // over was dropped onto the column itself
+                    let response = column.interact(
+                        column.min_rect().union(column.max_rect()),
+                        egui::Id::new(("ColumnDropZone", index)),
+                        egui::Sense::hover(),
+                    );
+                    if let Some(dragged) = response.dnd_hover_payload::<i32>() {
+                        if !cache[index].contains(&dragged) {
+                            column.painter().rect_stroke(
+                                response.rect,
+                                column.visuals().noninteractive().rounding,
+                                column.visuals().widgets.active.bg_stroke,
+                            );
+                        }
+                    }
+                    if let Some(dragged) = response.dnd_release_payload::<i32>() {
+                        self.summary_actions_pending
+                            .push(SummaryAction::MoveToColumn(*dragged, index));
+                    }
+                }
             });
         }
     }
@@ -155,6 +254,20 @@ impl KanbanRS {
             //     200.0,
             //     qs.cached_ready.len(),
             //     |ui, range| {
+            if !qs.quick_wins.is_empty() {
+                egui::CollapsingHeader::new("Quick wins")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.document.read().layout_id_list(
+                            ui,
+                            &qs.quick_wins,
+                            &mut self.hovered_task,
+                            &mut self.summary_actions_pending,
+                            "QuickWins",
+                        );
+                    });
+                ui.separator();
+            }
             self.document.read().layout_id_list(
                 ui,
                 &qs.cached_ready,
@@ -171,16 +284,26 @@ impl KanbanRS {
             ui.horizontal(|ui| {
                 let label = ui.label("Search");
                 ui.text_edit_singleline(&mut search_state.search_prompt)
-                    .labelled_by(label.id);
-                search_state.update(&doc);
+                    .labelled_by(label.id)
+                    .on_hover_text(
+                        "Look in one field with name:, desc:, tag:, cat: or prio:, \
+                        like name:parser",
+                    );
+                if ui
+                    .checkbox(&mut search_state.sort_first, "Sort first")
+                    .on_hover_text("Order by the sort, and only then by how well tasks match")
+                    .changed()
+                {
+                    search_state.force_update();
+                }
+                search_state.update(&doc, &self.filter, &self.sorting_type);
             });
 
-            doc.layout_id_list(
+            search_state.show_results(
                 ui,
-                &search_state.matched_ids,
+                &doc,
                 &mut self.hovered_task,
                 &mut self.summary_actions_pending,
-                "SearchArea",
             );
         }
     }
@@ -228,16 +351,16 @@ pub mod test {
         use chrono::Utc;
 
         let children = vec![vec![1], Vec::new(), vec![3]];
-        let mut document = kanban::tests::make_document_easy(4, &children);
+        let mut document = kanban::testing::make_document_easy(4, &children);
         {
             let mut task = document.get_task(1).unwrap().clone();
             task.completed = Some(Utc::now());
             document.replace_task(&task);
         }
-        let mut layout = KanbanDocumentLayout::Columnar([Vec::new(), vec![], vec![]]);
+        let mut layout = KanbanDocumentLayout::Columnar(Vec::new());
         layout.update_cache(
             &document,
-            &ItemSort::None,
+            &ItemSort::default(),
             &egui::Style::default(),
             &KanbanFilter::None,
         );
@@ -0,0 +1,514 @@
+//! Commands that operate on a document from the command line without opening a window.
+//!
+//! These apply the same document operations as the summary actions in the interface, so a
+//! document edited from a script is indistinguishable from one edited by hand.
+use super::kanban::{
+    self,
+    estimate::{format_estimate, parse_estimate},
+    filter::KanbanFilter,
+    persistence::{self, DocumentError},
+    query::Query,
+    queue_view::QueueState,
+    recovery, schedule,
+    sorting::ItemSort,
+    time_report::{format_hours, Grouping, TimeReport},
+    timesheet, KanbanDocument, KanbanId, KanbanItem, Status,
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc, Weekday};
+use clap::{Subcommand, ValueEnum};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Add a new task and print its id
+    Add {
+        name: String,
+        #[arg(short, long)]
+        description: Option<String>,
+        #[arg(short, long)]
+        category: Option<String>,
+        #[arg(short, long)]
+        priority: Option<String>,
+        /// A tag to apply to the task, may be given more than once
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+        /// Add the new task as a child of this task
+        #[arg(long)]
+        parent: Option<KanbanId>,
+        /// When the task is due, as `2024-05-31` or `2024-05-31 17:00` in local time
+        #[arg(long)]
+        due: Option<String>,
+        /// Keep the task out of the queue until this date, written like `--due`
+        #[arg(long)]
+        start_after: Option<String>,
+        /// How long the task is expected to take, as `1h 30m`, `45m` or `1.5` hours
+        #[arg(long)]
+        estimate: Option<String>,
+    },
+    /// List the tasks in the document
+    List {
+        #[arg(short, long, value_enum)]
+        status: Option<StatusArg>,
+        /// Only the tasks matching a query, like `cat:bug AND NOT tag:wontfix`
+        #[arg(short, long)]
+        query: Option<String>,
+    },
+    /// Mark a task as completed
+    Complete {
+        id: KanbanId,
+        /// Mark the task as not completed instead
+        #[arg(long)]
+        reopen: bool,
+    },
+    /// Make `child` a child task of `parent`
+    Link { parent: KanbanId, child: KanbanId },
+    /// Remove a task from the document
+    Remove { id: KanbanId },
+    /// Show everything about a single task
+    Show { id: KanbanId },
+    /// List the ready tasks, most important first
+    Queue,
+    /// Total the time logged over a range of days
+    Report {
+        /// The first day counted, as `2024-05-31`, the start of this week if not given
+        #[arg(long)]
+        from: Option<String>,
+        /// The last day counted, today if not given
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = GroupingArg::Day)]
+        by: GroupingArg,
+        /// Print the report as CSV
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Write every time entry out, for spreadsheets or calendars
+    Timesheet {
+        #[arg(short, long, value_enum, default_value_t = TimesheetFormat::Csv)]
+        format: TimesheetFormat,
+        /// Write to this file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check the document for problems, such as cycles or references to missing tasks
+    Check {
+        /// Fix the problems that were found
+        #[arg(long)]
+        repair: bool,
+    },
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum StatusArg {
+    Ready,
+    Blocked,
+    Completed,
+}
+impl From<StatusArg> for Status {
+    fn from(value: StatusArg) -> Self {
+        match value {
+            StatusArg::Ready => Status::Ready,
+            StatusArg::Blocked => Status::Blocked,
+            StatusArg::Completed => Status::Completed,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum GroupingArg {
+    Day,
+    Week,
+    Category,
+    Tag,
+    Tree,
+}
+impl From<GroupingArg> for Grouping {
+    fn from(value: GroupingArg) -> Self {
+        match value {
+            GroupingArg::Day => Grouping::Day,
+            GroupingArg::Week => Grouping::Week,
+            GroupingArg::Category => Grouping::Category,
+            GroupingArg::Tag => Grouping::Tag,
+            GroupingArg::Tree => Grouping::Tree,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum TimesheetFormat {
+    Csv,
+    /// iCalendar, with the entries that have a start and an end as events
+    Ics,
+}
+/// Run a command against the document at `path`, saving it if the command changed anything.
+pub fn run(path: &Path, command: Command) -> Result<(), String> {
+    let mut document = match persistence::open(path) {
+        Ok(document) => document,
+        // Checking is the one command that makes sense on a damaged document
+        Err(DocumentError::Integrity { document, .. })
+            if matches!(command, Command::Check { .. }) =>
+        {
+            *document
+        }
+        Err(x @ DocumentError::Integrity { .. }) => {
+            return Err(format!(
+                "Could not open '{}': {}\nRun `kanbanrs {} check --repair` to repair it.",
+                path.display(),
+                x,
+                path.display()
+            ))
+        }
+        Err(x) => return Err(format!("Could not open '{}': {}", path.display(), x)),
+    };
+    if apply(&mut document, command)? {
+        if recovery::has_unsaved_changes(path) {
+            eprintln!(
+                "Warning: '{}' has unsaved changes from the interface, restoring them will undo \
+                 this change",
+                path.display()
+            );
+        }
+        persistence::save(path, &document)
+            .map_err(|x| format!("Could not save '{}': {}", path.display(), x))?;
+    }
+    Ok(())
+}
+/// Apply a command to the document, returning true if the document was modified.
+fn apply(document: &mut KanbanDocument, command: Command) -> Result<bool, String> {
+    match command {
+        Command::Add {
+            name,
+            description,
+            category,
+            priority,
+            tags,
+            parent,
+            due,
+            start_after,
+            estimate,
+        } => {
+            let due = due
+                .map(|x| parse_date(&x, schedule::end_of_day()))
+                .transpose()?;
+            let start_after = start_after
+                .map(|x| parse_date(&x, NaiveTime::MIN))
+                .transpose()?;
+            let estimate = estimate
+                .map(|x| {
+                    parse_estimate(&x).ok_or_else(|| {
+                        format!("'{}' isn't a duration, write it like 1h 30m or 45m", x)
+                    })
+                })
+                .transpose()?;
+            if let Some(priority) = &priority {
+                if !document
+                    .get_sorted_priorities()
+                    .iter()
+                    .any(|(name, _)| *name == priority)
+                {
+                    return Err(format!("No priority named '{}'", priority));
+                }
+            }
+            let mut parent_task = parent
+                .map(|id| get_task(document, id).cloned())
+                .transpose()?;
+            let mut new_task = document.get_new_task();
+            new_task.name = name;
+            new_task.description = description.unwrap_or_default();
+            new_task.priority = priority;
+            new_task.tags = tags;
+            new_task.due = due;
+            new_task.start_after = start_after;
+            new_task.estimate = estimate;
+            if let Some(parent_task) = &parent_task {
+                new_task.inherit(parent_task, document);
+            }
+            if category.is_some() {
+                new_task.category = category;
+            }
+            document.replace_task(&new_task);
+            if let Some(parent_task) = parent_task.as_mut() {
+                parent_task.add_child(&new_task);
+                document.replace_task(parent_task);
+            }
+            println!("{}", new_task.id);
+            Ok(true)
+        }
+        Command::List { status, query } => {
+            let status = status.map(Status::from);
+            let query = query
+                .map(|x| Query::parse(&x))
+                .transpose()
+                .map_err(|x| x.to_string())?;
+            if let Some(query) = &query {
+                query.check(document)?;
+            }
+            for task in document.get_tasks() {
+                if status
+                    .as_ref()
+                    .is_some_and(|x| *x != document.task_status(&task.id))
+                    || query.as_ref().is_some_and(|x| !x.matches(task, document))
+                {
+                    continue;
+                }
+                print_line(document, task);
+            }
+            Ok(false)
+        }
+        Command::Complete { id, reopen } => {
+            let mut task = get_task(document, id)?.clone();
+            // Either way it's safe to run again, leaving a task that's already done as it was
+            if reopen {
+                if task.completed.is_none() {
+                    return Ok(false);
+                }
+                task.completed = None;
+                document.replace_task(&task);
+            } else if document.complete_task(id).is_none() {
+                return Ok(false);
+            }
+            Ok(true)
+        }
+        Command::Link { parent, child } => {
+            let parent_task = get_task(document, parent)?;
+            let child_task = get_task(document, child)?;
+            if !document.can_add_as_child(parent_task, child_task) {
+                return Err(format!(
+                    "Adding {} as a child of {} would create a cycle",
+                    child, parent
+                ));
+            }
+            let mut task = parent_task.clone();
+            task.child_tasks.insert(child);
+            document.replace_task(&task);
+            Ok(true)
+        }
+        Command::Remove { id } => {
+            let task = get_task(document, id)?.clone();
+            document.remove_task(&task);
+            Ok(true)
+        }
+        Command::Show { id } => {
+            let task = get_task(document, id)?;
+            print_line(document, task);
+            if let Some(completed) = task.completed {
+                println!("Completed: {}", completed);
+            }
+            if let Some(due) = task.due {
+                let overdue = if task.is_overdue(Utc::now()) {
+                    " (overdue)"
+                } else {
+                    ""
+                };
+                println!("Due: {}{}", schedule::format_date(&due), overdue);
+            }
+            if let Some(start_after) = task.start_after {
+                println!("Starts: {}", schedule::format_date(&start_after));
+            }
+            if let Some(recurrence) = &task.recurrence {
+                println!("Repeats: {}", recurrence);
+            }
+            if !task.tags.is_empty() {
+                println!("Tags: {}", task.tags.join(", "));
+            }
+            let spent = task.time_records.duration();
+            if !spent.is_zero() {
+                println!(
+                    "Time spent: {}h {}m",
+                    spent.num_hours(),
+                    spent.num_minutes() % 60
+                );
+            }
+            if let Some(estimate) = &task.estimate {
+                println!("Estimate: {}", format_estimate(estimate));
+            }
+            let rollup = document.rollup(id);
+            if !rollup.estimated.is_zero() {
+                println!(
+                    "With child tasks: {} estimated, {} spent, {} left",
+                    format_estimate(&rollup.estimated),
+                    format_estimate(&rollup.spent),
+                    format_estimate(&rollup.remaining)
+                );
+            }
+            for child in task.child_tasks.iter() {
+                print!("Child: ");
+                print_line(document, get_task(document, *child)?);
+            }
+            for parent in document.parents_of(id) {
+                print!("Parent: ");
+                print_line(document, parent);
+            }
+            if !task.description.is_empty() {
+                println!("\n{}", task.description);
+            }
+            Ok(false)
+        }
+        Command::Queue => {
+            let mut queue = QueueState::new();
+            queue.update(document, &KanbanFilter::None, &ItemSort::default());
+            for id in queue.cached_ready.iter() {
+                print_line(document, get_task(document, *id)?);
+            }
+            Ok(false)
+        }
+        Command::Report { from, to, by, csv } => {
+            let today = Local::now().date_naive();
+            let from = match from {
+                Some(x) => parse_day(&x)?,
+                None => today.week(Weekday::Mon).first_day(),
+            };
+            let to = to.map(|x| parse_day(&x)).transpose()?.unwrap_or(today);
+            let report = TimeReport::new(document, from, to, by.into());
+            if csv {
+                print!("{}", report.to_csv());
+                return Ok(false);
+            }
+            for (name, duration) in report.rows.iter() {
+                println!("{:>8}h {}", format_hours(duration), name);
+            }
+            println!("{:>8}h Total", format_hours(&report.total));
+            if !report.undated.is_zero() {
+                println!(
+                    "{}h entered by hand isn't on any day and isn't included",
+                    format_hours(&report.undated)
+                );
+            }
+            Ok(false)
+        }
+        Command::Timesheet { format, output } => {
+            let text = match format {
+                TimesheetFormat::Csv => timesheet::to_csv(document),
+                TimesheetFormat::Ics => timesheet::to_ics(document),
+            };
+            match output {
+                Some(path) => fs::write(&path, text)
+                    .map_err(|x| format!("Could not write '{}': {}", path.display(), x))?,
+                None => print!("{}", text),
+            }
+            Ok(false)
+        }
+        Command::Check { repair } => {
+            let problems = if repair {
+                document.repair()
+            } else {
+                document.validate()
+            };
+            for problem in problems.iter() {
+                println!("{}", problem);
+            }
+            if repair || problems.is_empty() {
+                Ok(!problems.is_empty())
+            } else {
+                Err(format!(
+                    "Found {} problems, run with --repair to fix them",
+                    problems.len()
+                ))
+            }
+        }
+    }
+}
+fn get_task(document: &KanbanDocument, id: KanbanId) -> Result<&KanbanItem, String> {
+    document
+        .get_task(id)
+        .ok_or_else(|| format!("No task with id {}", id))
+}
+fn parse_date(text: &str, default_time: NaiveTime) -> Result<DateTime<Utc>, String> {
+    schedule::parse_date(text, default_time).ok_or_else(|| {
+        format!(
+            "'{}' is not a date, expected something like 2024-05-31 or 2024-05-31 17:00",
+            text
+        )
+    })
+}
+fn parse_day(text: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| {
+        format!(
+            "'{}' is not a date, expected something like 2024-05-31",
+            text
+        )
+    })
+}
+fn print_line(document: &KanbanDocument, task: &KanbanItem) {
+    let status = match document.task_status(&task.id) {
+        kanban::Status::Ready => "ready",
+        kanban::Status::Blocked => "blocked",
+        kanban::Status::Completed => "completed",
+    };
+    print!("{:>5} [{}] {}", task.id, status, task.name);
+    if let Some(state) = &task.state {
+        print!(" in {}", state);
+    }
+    if let Some(category) = &task.category {
+        print!(" ({})", category);
+    }
+    if let Some(priority) = &task.priority {
+        print!(" <{}>", priority);
+    }
+    println!();
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn add(document: &mut KanbanDocument, name: &str, parent: Option<KanbanId>) {
+        apply(
+            document,
+            Command::Add {
+                name: name.into(),
+                description: None,
+                category: None,
+                priority: None,
+                tags: Vec::new(),
+                parent,
+                due: None,
+                start_after: None,
+                estimate: None,
+            },
+        )
+        .unwrap();
+    }
+    #[test]
+    fn test_add_with_parent() {
+        let mut document = KanbanDocument::new();
+        add(&mut document, "Parent", None);
+        add(&mut document, "Child", Some(0));
+        assert!(document.get_task(0).unwrap().child_tasks.contains(&1));
+        assert_eq!(document.task_status(&0), Status::Blocked);
+        apply(
+            &mut document,
+            Command::Complete {
+                id: 1,
+                reopen: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(document.task_status(&0), Status::Ready);
+        let completed = document.get_task(1).unwrap().completed;
+        assert_eq!(
+            apply(
+                &mut document,
+                Command::Complete {
+                    id: 1,
+                    reopen: false,
+                },
+            ),
+            Ok(false)
+        );
+        assert_eq!(document.get_task(1).unwrap().completed, completed);
+    }
+    #[test]
+    fn test_link_refuses_cycles() {
+        let mut document = KanbanDocument::new();
+        add(&mut document, "a", None);
+        add(&mut document, "b", Some(0));
+        assert!(apply(
+            &mut document,
+            Command::Link {
+                parent: 1,
+                child: 0
+            }
+        )
+        .is_err());
+        assert!(document.get_task(1).unwrap().child_tasks.is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+//! A month or week calendar, with tasks placed on the day they're due, or the day they were
+//! completed, and the time logged on each day.
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Days, Months, NaiveDate, TimeDelta, Weekday};
+use filter::KanbanFilter;
+use sorting::ItemSort;
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalendarSpan {
+    Month,
+    Week,
+}
+#[derive(Clone)]
+pub struct Calendar {
+    pub span: CalendarSpan,
+    /// Any day in the month or week being shown
+    pub anchor: NaiveDate,
+    /// The tasks placed on each day being shown
+    tasks: BTreeMap<NaiveDate, Vec<KanbanId>>,
+    /// The time logged on each day being shown
+    logged: BTreeMap<NaiveDate, TimeDelta>,
+}
+impl Default for Calendar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Calendar {
+    pub fn new() -> Self {
+        Calendar {
+            span: CalendarSpan::Month,
+            anchor: Local::now().date_naive(),
+            tasks: BTreeMap::new(),
+            logged: BTreeMap::new(),
+        }
+    }
+    /// The first and last days shown, whole weeks from Monday to Sunday.
+    pub fn range(&self) -> (NaiveDate, NaiveDate) {
+        let (first, last) = match self.span {
+            CalendarSpan::Week => (self.anchor, self.anchor),
+            CalendarSpan::Month => {
+                let first = self.anchor.with_day(1).unwrap();
+                (first, first + Months::new(1) - Days::new(1))
+            }
+        };
+        (
+            first.week(Weekday::Mon).first_day(),
+            last.week(Weekday::Mon).last_day(),
+        )
+    }
+    /// Move forward or back by a whole month or week.
+    pub fn step(&mut self, forward: bool) {
+        self.anchor = match (self.span, forward) {
+            (CalendarSpan::Month, true) => self.anchor + Months::new(1),
+            (CalendarSpan::Month, false) => self.anchor - Months::new(1),
+            (CalendarSpan::Week, true) => self.anchor + Days::new(7),
+            (CalendarSpan::Week, false) => self.anchor - Days::new(7),
+        };
+    }
+    /// The tasks placed on `day`, completed tasks on the day they were completed and the
+    /// others on the day they're due.
+    pub fn tasks_on(&self, day: NaiveDate) -> &[KanbanId] {
+        self.tasks.get(&day).map_or(&[], |x| x.as_slice())
+    }
+    pub fn logged_on(&self, day: NaiveDate) -> TimeDelta {
+        self.logged.get(&day).copied().unwrap_or_default()
+    }
+    pub fn update(&mut self, document: &KanbanDocument, sort: &ItemSort, filter: &KanbanFilter) {
+        self.tasks.clear();
+        self.logged.clear();
+        let (first, last) = self.range();
+        let shown = |day: &NaiveDate| (first..=last).contains(day);
+        for task in document.get_tasks() {
+            if !filter.matches(task, document) {
+                continue;
+            }
+            if let Some(day) = task
+                .completed
+                .or(task.due)
+                .map(|x| x.with_timezone(&Local).date_naive())
+                .filter(shown)
+            {
+                self.tasks.entry(day).or_default().push(task.id);
+            }
+            for (entry, _) in task.time_records.entries.iter() {
+                for (day, duration) in entry.per_day().into_iter() {
+                    if shown(&day) {
+                        *self.logged.entry(day).or_default() += duration;
+                    }
+                }
+            }
+        }
+        self.sort(document, sort);
+    }
+    pub fn sort(&mut self, document: &KanbanDocument, sort: &ItemSort) {
+        self.tasks
+            .values_mut()
+            .for_each(|ids| sort.sort_by(ids, document));
+    }
+    /// Show the calendar, returning true if it needs updating.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        document: &KanbanDocument,
+        actions: &mut Vec<SummaryAction>,
+        hovered_item: &mut Option<KanbanId>,
+    ) -> bool {
+        let mut needs_update = false;
+        ui.horizontal(|ui| {
+            if ui.button("⏴").on_hover_text("Previous").clicked() {
+                self.step(false);
+                needs_update = true;
+            }
+            if ui.button("Today").clicked() {
+                self.anchor = Local::now().date_naive();
+                needs_update = true;
+            }
+            if ui.button("⏵").on_hover_text("Next").clicked() {
+                self.step(true);
+                needs_update = true;
+            }
+            needs_update |= ui
+                .radio_value(&mut self.span, CalendarSpan::Month, "Month")
+                .changed();
+            needs_update |= ui
+                .radio_value(&mut self.span, CalendarSpan::Week, "Week")
+                .changed();
+            ui.heading(match self.span {
+                CalendarSpan::Month => self.anchor.format("%B %Y").to_string(),
+                CalendarSpan::Week => {
+                    let (first, last) = self.range();
+                    format!("{} - {}", first.format("%B %d"), last.format("%B %d %Y"))
+                }
+            });
+        });
+        let (first, last) = self.range();
+        let today = Local::now().date_naive();
+        ui.columns(7, |columns| {
+            for (column, day) in columns.iter_mut().zip(first.iter_days()) {
+                column.label(RichText::new(day.format("%A").to_string()).strong());
+            }
+        });
+        ScrollArea::vertical().id_salt("Calendar").show(ui, |ui| {
+            for week in first.iter_weeks().take_while(|x| *x <= last) {
+                ui.separator();
+                ui.columns(7, |columns| {
+                    for (column, day) in columns.iter_mut().zip(week.iter_days()) {
+                        let mut text = RichText::new(day.format("%d").to_string());
+                        if day == today {
+                            text = text.strong().underline();
+                        }
+                        if self.span == CalendarSpan::Month && day.month() != self.anchor.month() {
+                            text = text.weak();
+                        }
+                        if column.link(text).on_hover_text("Show this week").clicked() {
+                            self.anchor = day;
+                            self.span = CalendarSpan::Week;
+                            needs_update = true;
+                        }
+                        let logged = self.logged_on(day);
+                        if !logged.is_zero() {
+                            column.label(format!(
+                                "{}h {}m logged",
+                                logged.num_hours(),
+                                logged.num_minutes() % 60
+                            ));
+                        }
+                        for id in self.tasks_on(day) {
+                            if let Some(task) = document.get_task(*id) {
+                                column.push_id(id, |ui| {
+                                    actions.push(task.summary(document, hovered_item, ui));
+                                });
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        needs_update
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sorting::{Direction, SortKey};
+    use time_tracking::TimeEntry;
+    #[test]
+    fn test_update() {
+        let mut document = tests::make_document_easy(3, &[]);
+        let day = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let noon = day
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+            .to_utc();
+        let mut due = document.get_task(0).unwrap().clone();
+        due.due = Some(noon);
+        document.replace_task(&due);
+        let mut completed = document.get_task(1).unwrap().clone();
+        completed.due = Some(noon + TimeDelta::days(60));
+        completed.completed = Some(noon);
+        completed
+            .time_records
+            .entries
+            .push((TimeEntry::Concluded(noon, noon + TimeDelta::hours(2)), None));
+        document.replace_task(&completed);
+
+        let mut calendar = Calendar::new();
+        calendar.anchor = day;
+        calendar.update(
+            &document,
+            &ItemSort::by(SortKey::Id, Direction::Ascending),
+            &KanbanFilter::None,
+        );
+        assert_eq!(calendar.range().0.weekday(), Weekday::Mon);
+        assert_eq!(calendar.tasks_on(day), &[0, 1]);
+        assert_eq!(calendar.logged_on(day), TimeDelta::hours(2));
+
+        calendar.step(true);
+        calendar.update(
+            &document,
+            &ItemSort::by(SortKey::Id, Direction::Ascending),
+            &KanbanFilter::None,
+        );
+        assert!(calendar.tasks_on(day).is_empty());
+    }
+}
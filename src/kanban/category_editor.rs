@@ -17,6 +17,11 @@ pub struct State {
     pub open: bool,
     dummy_document: KanbanDocument,
 }
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl State {
     pub fn new() -> Self {
         let mut result = State {
@@ -42,7 +47,12 @@ impl State {
         ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
             if self.current_category_name != self.selected_category_name {
                 self.current_category_name = self.selected_category_name.clone();
-                self.style = document.categories[&self.current_category_name];
+                // The category may have been removed by undoing its creation
+                self.style = document
+                    .categories
+                    .get(&self.current_category_name)
+                    .copied()
+                    .unwrap_or_default();
             }
             ui.columns(2, |columns| {
                 columns[0].horizontal(|ui| {
@@ -1,7 +1,9 @@
 use std::borrow::BorrowMut;
 
-use super::{time_tracking, KanbanDocument, KanbanId, KanbanItem};
-use chrono::TimeDelta;
+use super::{
+    estimate, recurrence::Recurrence, schedule, time_tracking, KanbanDocument, KanbanId, KanbanItem,
+};
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc, Weekday};
 use eframe::egui::{self, Button, ComboBox, RichText, ScrollArea};
 use std::sync::mpsc::Sender;
 #[derive(Clone)]
@@ -17,6 +19,9 @@ pub struct State {
     new_time_entry: TimeDelta,
     new_time_descr: String,
     time_entry_under_edit: Option<usize>,
+    due: String,
+    start_after: String,
+    estimate: String,
     transmitter: Sender<EditorRequest>,
 }
 pub fn state_from(item: &KanbanItem, tx: Sender<EditorRequest>) -> State {
@@ -32,16 +37,34 @@ pub fn state_from(item: &KanbanItem, tx: Sender<EditorRequest>) -> State {
         new_time_descr: String::new(),
         new_time_entry: TimeDelta::new(0, 0).unwrap(),
         time_entry_under_edit: None,
+        due: item
+            .due
+            .as_ref()
+            .map(schedule::format_date)
+            .unwrap_or_default(),
+        start_after: item
+            .start_after
+            .as_ref()
+            .map(schedule::format_date)
+            .unwrap_or_default(),
+        estimate: item
+            .estimate
+            .as_ref()
+            .map(estimate::format_estimate)
+            .unwrap_or_default(),
         transmitter: tx,
     }
 }
 #[derive(Clone, Debug)]
 pub enum EditorRequest {
     NoRequest,
-    NewItem(KanbanItem, KanbanItem),
+    /// The parent and the new child, boxed as the pair is much larger than the other requests
+    NewItem(Box<KanbanItem>, Box<KanbanItem>),
     OpenItem(KanbanItem),
     DeleteItem(KanbanItem),
     UpdateItem(KanbanItem),
+    /// Start timing the task with the description given, or stop its timer if it's running
+    ToggleTimer(KanbanId, Option<String>),
 }
 pub fn editor(ui: &mut egui::Ui, document: &KanbanDocument, state: &mut State) -> bool {
     let mut create_child = false;
@@ -91,6 +114,42 @@ pub fn editor(ui: &mut egui::Ui, document: &KanbanDocument, state: &mut State) -
                         }
                     })
             });
+            if !document.workflow().is_empty() {
+                ui.horizontal(|ui| {
+                    ui.label("State");
+                    let mut new_state = state.item_copy.state.clone();
+                    ComboBox::from_id_salt("State")
+                        .selected_text(new_state.as_deref().unwrap_or("None"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut new_state, None, "None");
+                            for workflow_state in document.workflow() {
+                                ui.selectable_value(
+                                    &mut new_state,
+                                    Some(workflow_state.name.clone()),
+                                    &workflow_state.name,
+                                );
+                            }
+                        });
+                    if new_state != state.item_copy.state {
+                        state.item_copy.set_state(new_state, document);
+                    }
+                });
+            }
+            date_field(
+                ui,
+                "Due",
+                &mut state.due,
+                &mut state.item_copy.due,
+                schedule::end_of_day(),
+            );
+            date_field(
+                ui,
+                "Start after",
+                &mut state.start_after,
+                &mut state.item_copy.start_after,
+                NaiveTime::MIN,
+            );
+            recurrence_field(ui, &mut state.item_copy.recurrence);
             ui.heading("Description");
             ui.text_edit_multiline(&mut state.item_copy.description);
             ui.columns(2, |columns| {
@@ -131,9 +190,11 @@ pub fn editor(ui: &mut egui::Ui, document: &KanbanDocument, state: &mut State) -
                                     .get_tasks()
                                     .filter(|x| document.can_add_as_child(&state.item_copy, x))
                                     .collect();
-                                let c = super::sorting::ItemSort::Id;
-                                task.sort_by(|a, b| c.cmp_by(a, b));
-                                task.reverse();
+                                let c = super::sorting::ItemSort::by(
+                                    super::sorting::SortKey::Id,
+                                    super::sorting::Direction::Descending,
+                                );
+                                task.sort_by(|a, b| c.cmp_by(a, b, document));
                                 task.sort_by(|a, b| {
                                     super::sorting::task_comparison_completed_last(a, b)
                                 });
@@ -230,7 +291,10 @@ pub fn editor(ui: &mut egui::Ui, document: &KanbanDocument, state: &mut State) -
         state.item_copy.add_child(&new_child);
         state
             .transmitter
-            .send(EditorRequest::NewItem(state.item_copy.clone(), new_child))
+            .send(EditorRequest::NewItem(
+                Box::new(state.item_copy.clone()),
+                Box::new(new_child),
+            ))
             .unwrap();
     }
     if let Some(task_to_edit) = open_task {
@@ -244,6 +308,88 @@ pub fn editor(ui: &mut egui::Ui, document: &KanbanDocument, state: &mut State) -
     needs_update
 }
 
+/// A text field for an optional date, which only changes the date once the text can be read as
+/// one, or is cleared.
+fn date_field(
+    ui: &mut egui::Ui,
+    label: &str,
+    text: &mut String,
+    date: &mut Option<DateTime<Utc>>,
+    default_time: NaiveTime,
+) {
+    ui.horizontal(|ui| {
+        let label = ui.label(label);
+        let changed = ui
+            .text_edit_singleline(text)
+            .on_hover_text("e.g. 2024-05-31 or 2024-05-31 17:00, leave blank for none")
+            .labelled_by(label.id)
+            .changed();
+        if text.trim().is_empty() {
+            *date = None;
+            return;
+        }
+        match schedule::parse_date(text, default_time) {
+            Some(parsed) if changed => *date = Some(parsed),
+            Some(_) => (),
+            None => {
+                ui.label(RichText::new("Not a date").color(ui.visuals().error_fg_color));
+            }
+        }
+    });
+}
+/// Choosing whether the task is created again once it's completed, and how often.
+fn recurrence_field(ui: &mut egui::Ui, recurrence: &mut Option<Recurrence>) {
+    let choices = [
+        ("Never", None),
+        ("Daily", Some(Recurrence::Daily)),
+        ("Weekly", Some(Recurrence::Weekly(Vec::new()))),
+        ("Every few days", Some(Recurrence::EveryNDays(2))),
+        ("Monthly", Some(Recurrence::Monthly(1))),
+    ];
+    let kind = |x: &Option<Recurrence>| x.as_ref().map(std::mem::discriminant);
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Repeats");
+        let current = choices
+            .iter()
+            .find(|(_, choice)| kind(choice) == kind(recurrence))
+            .map_or("Never", |(name, _)| name);
+        ComboBox::from_id_salt("Repeats")
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                for (name, choice) in choices.iter() {
+                    if ui.selectable_label(*name == current, *name).clicked() && *name != current {
+                        *recurrence = choice.clone();
+                    }
+                }
+            });
+        match recurrence {
+            Some(Recurrence::Weekly(days)) => {
+                for day in (0..7).filter_map(|x| Weekday::try_from(x).ok()) {
+                    let mut on = days.contains(&day);
+                    if ui.checkbox(&mut on, day.to_string()).changed() {
+                        days.retain(|x| *x != day);
+                        if on {
+                            days.push(day);
+                            days.sort_by_key(|x| x.num_days_from_monday());
+                        }
+                    }
+                }
+            }
+            Some(Recurrence::EveryNDays(days)) => {
+                ui.add(
+                    egui::DragValue::new(days)
+                        .range(1..=365)
+                        .prefix("every ")
+                        .suffix(" days"),
+                );
+            }
+            Some(Recurrence::Monthly(day)) => {
+                ui.add(egui::DragValue::new(day).range(1..=31).prefix("on day "));
+            }
+            _ => (),
+        }
+    });
+}
 fn display_tags(ui: &mut egui::Ui, state: &mut State) {
     ui.label("Tags");
     let mut removed_tag: Option<String> = None;
@@ -348,6 +494,7 @@ fn show_parents(
 }
 
 fn show_time_records(ui: &mut egui::Ui, state: &mut State, document: &KanbanDocument) {
+    estimate_ui(state, ui, document);
     time_entry_ui(state, ui);
     ScrollArea::vertical().show(ui, |ui| {
         produce_time_list(state, ui);
@@ -370,6 +517,49 @@ fn show_time_records(ui: &mut egui::Ui, state: &mut State, document: &KanbanDocu
         }
     });
 }
+/// The task's estimate, and the totals over it and its child tasks.
+fn estimate_ui(state: &mut State, ui: &mut egui::Ui, document: &KanbanDocument) {
+    use estimate::{format_estimate, parse_estimate};
+    ui.horizontal(|ui| {
+        let label = ui.label("Estimate");
+        let changed = ui
+            .text_edit_singleline(&mut state.estimate)
+            .on_hover_text("e.g. 1h 30m, 45m or 1.5, leave blank for none")
+            .labelled_by(label.id)
+            .changed();
+        if state.estimate.trim().is_empty() {
+            state.item_copy.estimate = None;
+            return;
+        }
+        match parse_estimate(&state.estimate) {
+            Some(parsed) if changed => state.item_copy.estimate = Some(parsed),
+            Some(_) => (),
+            None => {
+                ui.label(RichText::new("Not a duration").color(ui.visuals().error_fg_color));
+            }
+        }
+    });
+    if let Some(remaining) = state.item_copy.remaining_estimate() {
+        ui.label(format!("{} left", format_estimate(&remaining)));
+    }
+    // As last applied, the changes being made here aren't included
+    let rollup = document.rollup(state.item_copy.id);
+    if state.item_copy.child_tasks.is_empty() || rollup.estimated.is_zero() {
+        return;
+    }
+    ui.label(format!(
+        "With child tasks: {} estimated, {} spent, {} left",
+        format_estimate(&rollup.estimated),
+        format_estimate(&rollup.spent),
+        format_estimate(&rollup.remaining)
+    ));
+    if rollup.unestimated > 0 {
+        ui.label(format!(
+            "{} tasks without child tasks have no estimate",
+            rollup.unestimated
+        ));
+    }
+}
 fn time_entry_ui(state: &mut State, ui: &mut egui::Ui) {
     use chrono::TimeDelta;
     use time_tracking::*;
@@ -425,7 +615,11 @@ fn time_entry_ui(state: &mut State, ui: &mut egui::Ui) {
                 } else {
                     Some(state.new_time_descr.clone())
                 };
-                state.item_copy.time_records.handle_record_request(desc);
+                // Goes through the document, which stops the timers on the other tasks
+                state
+                    .transmitter
+                    .send(EditorRequest::ToggleTimer(state.item_copy.id, desc))
+                    .unwrap();
                 state.new_time_descr.clear();
             }
         });
@@ -0,0 +1,132 @@
+//! Estimates of how long tasks will take, and totals of them over a task and everything under it.
+use super::*;
+use chrono::TimeDelta;
+use std::collections::HashSet;
+
+/// Ready tasks with no more than this left of their estimate are suggested in the queue.
+pub const SMALL_TASK: TimeDelta = TimeDelta::minutes(30);
+
+/// The estimates and time spent over a task and everything under it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rollup {
+    /// The estimates of every task, completed or not
+    pub estimated: TimeDelta,
+    pub spent: TimeDelta,
+    /// What's left of the estimates of the tasks not completed yet
+    pub remaining: TimeDelta,
+    /// The tasks without child tasks that aren't completed and have no estimate, which the
+    /// remaining time doesn't account for
+    pub unestimated: usize,
+}
+impl KanbanItem {
+    /// What's left of the task's estimate after the time spent on it, none if it has no estimate.
+    pub fn remaining_estimate(&self) -> Option<TimeDelta> {
+        self.estimate
+            .map(|x| (x - self.time_records.duration()).max(TimeDelta::zero()))
+    }
+}
+impl KanbanDocument {
+    /// Total the estimates and time spent on the task and every task under it, counting tasks
+    /// reached along more than one path once.
+    pub fn rollup(&self, id: KanbanId) -> Rollup {
+        let mut result = Rollup::default();
+        let mut seen: HashSet<KanbanId> = HashSet::new();
+        self.on_tree(id, 0, |document, id, _| {
+            if !seen.insert(id) {
+                return;
+            }
+            let task = document.get_task(id).unwrap();
+            result.spent += task.time_records.duration();
+            result.estimated += task.estimate.unwrap_or_default();
+            if task.completed.is_some() {
+                return;
+            }
+            match task.remaining_estimate() {
+                Some(remaining) => result.remaining += remaining,
+                // Parent tasks are usually estimated through their children
+                None if task.child_tasks.is_empty() => result.unestimated += 1,
+                None => (),
+            }
+        });
+        result
+    }
+}
+/// A duration in hours and minutes, like `1h 30m`.
+pub fn format_estimate(duration: &TimeDelta) -> String {
+    match (duration.num_hours(), duration.num_minutes() % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+/// Read an estimate written as hours and minutes like `1h 30m`, `90m` or `2h`, or as a number
+/// of hours like `1.5`.
+pub fn parse_estimate(text: &str) -> Option<TimeDelta> {
+    let text = text.trim();
+    if let Ok(hours) = text.parse::<f64>() {
+        return (hours.is_finite() && hours >= 0.0)
+            .then(|| TimeDelta::seconds((hours * 3600.0).round() as i64));
+    }
+    let mut total = TimeDelta::zero();
+    let mut any = false;
+    for part in text.split_whitespace() {
+        let (number, unit) = part.split_at(part.find(|x: char| !x.is_ascii_digit())?);
+        let number: i64 = number.parse().ok()?;
+        total += match unit {
+            "h" => TimeDelta::hours(number),
+            "m" => TimeDelta::minutes(number),
+            _ => return None,
+        };
+        any = true;
+    }
+    any.then_some(total)
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time_tracking::TimeEntry;
+    #[test]
+    fn test_rollup() {
+        // Task 3 is under both 1 and 2
+        let mut document = tests::make_document_easy(4, &[vec![1, 2], vec![3], vec![3], vec![]]);
+        let mut estimated = |id: KanbanId, hours: i64, spent: i64| {
+            let mut task = document.get_task(id).unwrap().clone();
+            task.estimate = Some(TimeDelta::hours(hours));
+            task.time_records.entries.push((
+                TimeEntry::InstanteousDuration(TimeDelta::hours(spent)),
+                None,
+            ));
+            document.replace_task(&task);
+        };
+        estimated(1, 4, 1);
+        estimated(3, 2, 3);
+        let rollup = document.rollup(0);
+        assert_eq!(rollup.estimated, TimeDelta::hours(6));
+        assert_eq!(rollup.spent, TimeDelta::hours(4));
+        // Task 3 has gone over its estimate, which doesn't take from task 1's
+        assert_eq!(rollup.remaining, TimeDelta::hours(3));
+        assert_eq!(rollup.unestimated, 0);
+
+        let mut task = document.get_task(1).unwrap().clone();
+        task.completed = Some(Utc::now());
+        document.replace_task(&task);
+        let mut task = document.get_task(2).unwrap().clone();
+        task.child_tasks.clear();
+        document.replace_task(&task);
+        let rollup = document.rollup(0);
+        assert_eq!(rollup.remaining, TimeDelta::zero());
+        assert_eq!(rollup.unestimated, 1);
+    }
+    #[test]
+    fn test_parse_estimate() {
+        assert_eq!(parse_estimate("1.5"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_estimate("1h 30m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_estimate(" 45m "), Some(TimeDelta::minutes(45)));
+        assert_eq!(parse_estimate("2h"), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_estimate("h"), None);
+        assert_eq!(parse_estimate("2 days"), None);
+        assert_eq!(parse_estimate(""), None);
+        assert_eq!(format_estimate(&TimeDelta::minutes(90)), "1h 30m");
+        assert_eq!(format_estimate(&TimeDelta::minutes(45)), "45m");
+    }
+}
@@ -1,13 +1,54 @@
+#[cfg(feature = "gui")]
 use egui::{ComboBox, Ui};
 
+use super::query::{Query, QueryError};
 use super::*;
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredFilter", into = "StoredFilter")]
 pub enum KanbanFilter {
     None,
     ContainsString(String),
     MatchesCategory(String),
     RelatedTo(KanbanId),
     CompletionStatus(bool),
+    /// The query as written and as parsed, see [`query`]. Queries that can't be parsed don't
+    /// filter anything out.
+    Query(String, Result<Query, QueryError>),
+}
+
+/// How a filter is saved, queries are kept as written and parsed again when they're loaded.
+#[derive(Serialize, Deserialize)]
+enum StoredFilter {
+    None,
+    ContainsString(String),
+    MatchesCategory(String),
+    RelatedTo(KanbanId),
+    CompletionStatus(bool),
+    Query(String),
+}
+impl From<StoredFilter> for KanbanFilter {
+    fn from(value: StoredFilter) -> Self {
+        match value {
+            StoredFilter::None => Self::None,
+            StoredFilter::ContainsString(x) => Self::ContainsString(x),
+            StoredFilter::MatchesCategory(x) => Self::MatchesCategory(x),
+            StoredFilter::RelatedTo(x) => Self::RelatedTo(x),
+            StoredFilter::CompletionStatus(x) => Self::CompletionStatus(x),
+            StoredFilter::Query(x) => Self::query(&x),
+        }
+    }
+}
+impl From<KanbanFilter> for StoredFilter {
+    fn from(value: KanbanFilter) -> Self {
+        match value {
+            KanbanFilter::None => Self::None,
+            KanbanFilter::ContainsString(x) => Self::ContainsString(x),
+            KanbanFilter::MatchesCategory(x) => Self::MatchesCategory(x),
+            KanbanFilter::RelatedTo(x) => Self::RelatedTo(x),
+            KanbanFilter::CompletionStatus(x) => Self::CompletionStatus(x),
+            KanbanFilter::Query(x, _) => Self::Query(x),
+        }
+    }
 }
 
 impl Default for KanbanFilter {
@@ -15,6 +56,36 @@ impl Default for KanbanFilter {
         Self::None
     }
 }
+impl KanbanFilter {
+    pub fn query(text: &str) -> Self {
+        Self::Query(text.to_owned(), Query::parse(text))
+    }
+    pub fn matches(&self, item: &KanbanItem, document: &KanbanDocument) -> bool {
+        match self {
+            KanbanFilter::None => true,
+            KanbanFilter::ContainsString(str) => {
+                let mut s: String = String::new();
+                item.fill_searchable_buffer(&mut s);
+                s.contains(str)
+            }
+            KanbanFilter::MatchesCategory(category) => item
+                .category
+                .as_ref()
+                .is_some_and(|x| x.eq(category.as_str())),
+            Self::RelatedTo(id) => document.get_relation(*id, item.id) != TaskRelation::Unrelated,
+            Self::CompletionStatus(completion_status) => {
+                if *completion_status {
+                    item.completed.is_some()
+                } else {
+                    item.completed.is_none()
+                }
+            }
+            Self::Query(_, Ok(query)) => query.matches(item, document),
+            Self::Query(_, Err(_)) => true,
+        }
+    }
+}
+#[cfg(feature = "gui")]
 impl KanbanFilter {
     fn option_name(&self) -> &'static str {
         match self {
@@ -24,6 +95,7 @@ impl KanbanFilter {
             Self::RelatedTo(_) => "Related To",
             Self::CompletionStatus(true) => "Completed",
             Self::CompletionStatus(false) => "Uncompleted",
+            Self::Query(_, _) => "Query",
         }
     }
     pub fn show_ui(&mut self, ui: &mut Ui, document: &KanbanDocument) -> egui::Response {
@@ -46,6 +118,13 @@ impl KanbanFilter {
                     );
                     ui.selectable_value(self, Self::CompletionStatus(true), "Completed");
                     ui.selectable_value(self, Self::CompletionStatus(false), "Uncompleted");
+                    if ui
+                        .selectable_label(matches!(self, Self::Query(_, _)), "Query")
+                        .clicked()
+                        && !matches!(self, Self::Query(_, _))
+                    {
+                        *self = Self::query("");
+                    }
                 })
                 .response;
             // I need to report this to egui as this seems as if it shouldn't be necessary
@@ -60,6 +139,24 @@ impl KanbanFilter {
                 Self::MatchesCategory(ref mut str) => {
                     text_response = Some(ui.text_edit_singleline(str));
                 }
+                Self::Query(ref mut text, ref mut parsed) => {
+                    let text_edit = ui.text_edit_singleline(text).on_hover_text(
+                        "e.g. cat:bug AND NOT tag:wontfix AND prio>=Medium AND completed:none\n\n\
+                         Fields: name, desc, cat, tag, state, prio, status, completed, due, \
+                         start, spent, estimate and related",
+                    );
+                    if text_edit.changed() {
+                        *parsed = Query::parse(text);
+                    }
+                    let problem = match parsed {
+                        Ok(query) => query.check(document).err(),
+                        Err(x) => Some(x.to_string()),
+                    };
+                    if let Some(problem) = problem {
+                        ui.label(RichText::new(problem).color(ui.visuals().error_fg_color));
+                    }
+                    text_response = Some(text_edit);
+                }
                 _ => {}
             }
             if let Some(tr) = text_response {
@@ -70,28 +167,6 @@ impl KanbanFilter {
         });
         response.unwrap()
     }
-    pub fn matches(&self, item: &KanbanItem, document: &KanbanDocument) -> bool {
-        match self {
-            KanbanFilter::None => true,
-            KanbanFilter::ContainsString(str) => {
-                let mut s: String = String::new();
-                item.fill_searchable_buffer(&mut s);
-                s.contains(str)
-            }
-            KanbanFilter::MatchesCategory(category) => item
-                .category
-                .as_ref()
-                .is_some_and(|x| x.eq(category.as_str())),
-            Self::RelatedTo(id) => document.get_relation(*id, item.id) != TaskRelation::Unrelated,
-            Self::CompletionStatus(completion_status) => {
-                if *completion_status {
-                    item.completed.is_some()
-                } else {
-                    item.completed.is_none()
-                }
-            }
-        }
-    }
 }
 #[cfg(test)]
 mod test {
@@ -1,4 +1,5 @@
 use super::*;
+use filter::KanbanFilter;
 #[derive(Clone)]
 pub struct Focus {
     pub cares_about: Option<KanbanId>,
@@ -13,7 +14,8 @@ impl Focus {
             ancestors: Vec::new(),
         }
     }
-    pub fn update(&mut self, document: &KanbanDocument) {
+    /// Find the focused task's relatives, leaving out those the filter doesn't match.
+    pub fn update(&mut self, document: &KanbanDocument, filter: &KanbanFilter) {
         if self.cares_about.is_none() {
             return;
         }
@@ -21,7 +23,7 @@ impl Focus {
         self.ancestors.clear();
         let subject = self.cares_about.unwrap();
         for task in document.get_tasks() {
-            if task.id == subject {
+            if task.id == subject || !filter.matches(task, document) {
                 continue;
             }
             match document.get_relation(subject, task.id) {
@@ -0,0 +1,243 @@
+//! Checking a document for contradictions, and repairing them.
+//!
+//! Documents edited by hand, or by scripts that don't go through [`KanbanDocument`], can end up
+//! in states the rest of the program assumes are impossible, such as cycles between tasks,
+//! which make [`KanbanDocument::task_status`] recurse forever.
+use super::*;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The task is stored under a different id than the one it carries.
+    MismatchedId { key: KanbanId, id: KanbanId },
+    /// The task depends on a task that isn't in the document.
+    MissingChild { parent: KanbanId, child: KanbanId },
+    /// Following the child tasks from `child` leads back around to `parent`.
+    Cycle { parent: KanbanId, child: KanbanId },
+    /// New tasks would be given ids that are already in use.
+    NextIdBehind {
+        next_id: KanbanId,
+        highest: KanbanId,
+    },
+    /// The task's priority isn't one of the document's priorities.
+    UnknownPriority { task: KanbanId, priority: String },
+}
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MismatchedId { key, id } => {
+                write!(f, "The task stored as {} says its id is {}", key, id)
+            }
+            Self::MissingChild { parent, child } => write!(
+                f,
+                "Task {} depends on task {}, which doesn't exist",
+                parent, child
+            ),
+            Self::Cycle { parent, child } => write!(
+                f,
+                "Task {} depends on task {}, which in turn depends on task {}",
+                parent, child, parent
+            ),
+            Self::NextIdBehind { next_id, highest } => write!(
+                f,
+                "The next task would be given id {}, but ids up to {} are in use",
+                next_id, highest
+            ),
+            Self::UnknownPriority { task, priority } => write!(
+                f,
+                "Task {} has the priority '{}', which isn't defined",
+                task, priority
+            ),
+        }
+    }
+}
+impl Diagnostic {
+    /// Whether the problem breaks the assumptions the rest of the program makes, rather than
+    /// only being untidy. Undefined priorities count as zero everywhere, and a next id that's
+    /// behind is moved along when the document is loaded.
+    pub fn is_damage(&self) -> bool {
+        match self {
+            Self::MismatchedId { .. } | Self::MissingChild { .. } | Self::Cycle { .. } => true,
+            Self::NextIdBehind { .. } | Self::UnknownPriority { .. } => false,
+        }
+    }
+}
+impl KanbanDocument {
+    /// Find every problem with the document.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut result = Vec::new();
+        for (key, task) in self.tasks.iter() {
+            if *key != task.id {
+                result.push(Diagnostic::MismatchedId {
+                    key: *key,
+                    id: task.id,
+                });
+            }
+        }
+        for (key, task) in self.tasks.iter() {
+            for child in task.child_tasks.iter() {
+                if !self.tasks.contains_key(child) {
+                    result.push(Diagnostic::MissingChild {
+                        parent: *key,
+                        child: *child,
+                    });
+                }
+            }
+        }
+        result.extend(
+            self.find_back_edges()
+                .into_iter()
+                .map(|(parent, child)| Diagnostic::Cycle { parent, child }),
+        );
+        if let Some(highest) = self.tasks.keys().next_back() {
+            let next_id = *self.next_id.read();
+            if next_id <= *highest {
+                result.push(Diagnostic::NextIdBehind {
+                    next_id,
+                    highest: *highest,
+                });
+            }
+        }
+        for (key, task) in self.tasks.iter() {
+            if let Some(priority) = &task.priority {
+                if !self.priorities.contains_key(priority) {
+                    result.push(Diagnostic::UnknownPriority {
+                        task: *key,
+                        priority: priority.clone(),
+                    });
+                }
+            }
+        }
+        result
+    }
+    /// Fix every problem found by [`Self::validate`], returning what was fixed.
+    ///
+    /// * Tasks take the id they're stored under.
+    /// * Dependencies on missing tasks are removed.
+    /// * Cycles are broken by removing the dependency that closes them.
+    /// * The next id is moved past the highest id in use.
+    /// * Undefined priorities are defined with a value of zero, the value they were treated
+    ///   as having anyway.
+    pub fn repair(&mut self) -> Vec<Diagnostic> {
+        let problems = self.validate();
+        problems.iter().for_each(|x| self.fix(x));
+        problems
+    }
+    /// Repair a single problem, as [`Self::repair`] does.
+    ///
+    /// Changes to dependencies and priorities are recorded, so a repair made within a
+    /// transaction can be undone. An id mismatch can't be, as an undo item can't put a task
+    /// back under a key other than its id.
+    pub fn fix(&mut self, problem: &Diagnostic) {
+        match problem {
+            Diagnostic::MismatchedId { key, .. } => {
+                self.tasks.get_mut(key).unwrap().id = *key;
+            }
+            Diagnostic::MissingChild { parent, child } | Diagnostic::Cycle { parent, child } => {
+                let mut task = self.tasks[parent].clone();
+                task.id = *parent;
+                task.child_tasks.remove(child);
+                self.replace_task(&task);
+            }
+            Diagnostic::NextIdBehind { highest, .. } => {
+                *self.next_id.write() = highest.checked_add(1).unwrap_or(KanbanId::MIN);
+            }
+            Diagnostic::UnknownPriority { priority, .. } => {
+                // Several tasks can share the priority
+                if !self.priorities.contains_key(priority) {
+                    self.replace_priority(priority, 0);
+                }
+            }
+        }
+    }
+    /// Find the dependencies that close a cycle, as (parent, child) pairs.
+    ///
+    /// Removing every one of them leaves the document without cycles.
+    fn find_back_edges(&self) -> Vec<(KanbanId, KanbanId)> {
+        let mut result = Vec::new();
+        let mut finished: HashSet<KanbanId> = HashSet::new();
+        let mut on_path: HashSet<KanbanId> = HashSet::new();
+        for root in self.tasks.keys() {
+            if finished.contains(root) {
+                continue;
+            }
+            // Each entry is a task along with the children that are yet to be visited.
+            let mut stack: Vec<(KanbanId, Vec<KanbanId>)> = vec![(*root, self.children_of(*root))];
+            on_path.insert(*root);
+            while let Some((current, remaining)) = stack.last_mut() {
+                let current = *current;
+                if let Some(child) = remaining.pop() {
+                    if on_path.contains(&child) {
+                        result.push((current, child));
+                    } else if !finished.contains(&child) {
+                        on_path.insert(child);
+                        stack.push((child, self.children_of(child)));
+                    }
+                } else {
+                    on_path.remove(&current);
+                    finished.insert(current);
+                    stack.pop();
+                }
+            }
+        }
+        result
+    }
+    /// The children of the task that exist, in reverse so they are visited in order.
+    fn children_of(&self, id: KanbanId) -> Vec<KanbanId> {
+        self.tasks[&id]
+            .child_tasks
+            .iter()
+            .rev()
+            .filter(|x| self.tasks.contains_key(x))
+            .copied()
+            .collect()
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_healthy_document() {
+        let document = tests::make_document_easy(4, &[vec![1, 2], vec![3], vec![3]]);
+        assert!(document.validate().is_empty());
+    }
+    #[test]
+    fn test_repair() {
+        let mut document = tests::make_document_easy(3, &[vec![1], vec![2]]);
+        // Close the loop, and make a mess of everything else.
+        document.tasks.get_mut(&2).unwrap().child_tasks.insert(0);
+        document.tasks.get_mut(&1).unwrap().child_tasks.insert(10);
+        document.tasks.get_mut(&0).unwrap().priority = Some("Urgent".into());
+        *document.next_id.write() = 1;
+        let problems = document.validate();
+        assert!(problems.contains(&Diagnostic::Cycle {
+            parent: 2,
+            child: 0
+        }));
+        assert!(problems.contains(&Diagnostic::MissingChild {
+            parent: 1,
+            child: 10
+        }));
+        assert!(problems.contains(&Diagnostic::NextIdBehind {
+            next_id: 1,
+            highest: 2
+        }));
+        assert!(problems.contains(&Diagnostic::UnknownPriority {
+            task: 0,
+            priority: "Urgent".into()
+        }));
+        document.begin_transaction();
+        assert_eq!(document.repair(), problems);
+        let undo = document.commit_transaction().unwrap();
+        assert!(document.validate().is_empty());
+        // This would never return if the cycle were still there.
+        assert_eq!(document.task_status(&0), Status::Blocked);
+        assert_eq!(document.get_next_id(), 3);
+
+        // Undoing the repair brings back the dependencies and priority, but not the next id
+        undo.apply(&mut document);
+        assert!(document.get_task(1).unwrap().child_tasks.contains(&10));
+        assert!(document.get_task(2).unwrap().child_tasks.contains(&0));
+        assert!(!document.priorities.contains_key("Urgent"));
+    }
+}
@@ -1,4 +1,5 @@
 use chrono::prelude::*;
+#[cfg(feature = "gui")]
 use eframe::egui::{self, Color32, Margin, Response, RichText, ScrollArea, Stroke, Vec2};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
@@ -7,30 +8,61 @@ use std::collections::btree_map::{Values, ValuesMut};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use time_tracking::TimeRecords;
 use undo::{DeletionEvent, UndoItem};
+#[cfg(feature = "gui")]
+pub mod calendar_layout;
+#[cfg(feature = "gui")]
 pub mod category_editor;
+pub mod estimate;
 pub mod filter;
 pub mod focused_layout;
+pub mod integrity;
+#[cfg(feature = "gui")]
 pub mod node_layout;
+pub mod persistence;
+#[cfg(feature = "gui")]
 pub mod priority_editor;
+pub mod query;
+pub mod recovery;
+pub mod recurrence;
+#[cfg(feature = "gui")]
+pub mod report_window;
+pub mod schedule;
+pub mod search;
 pub mod sorting;
+pub mod statistics;
+#[cfg(feature = "gui")]
+pub mod statistics_window;
+pub mod time_report;
 pub mod time_tracking;
+pub mod timesheet;
+#[cfg(feature = "gui")]
 pub mod tree_outline_layout;
 pub mod undo;
+pub mod views;
+pub mod workflow;
+#[cfg(feature = "gui")]
+pub mod workflow_editor;
 
 pub type KanbanId = i32;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum Status {
     Blocked,
     Completed,
     Ready,
 }
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 pub struct KanbanDocument {
     tasks: BTreeMap<KanbanId, KanbanItem>,
     priorities: HashMap<String, i32>,
     categories: HashMap<String, KanbanCategoryStyle>,
     next_id: RwLock<KanbanId>,
+    #[serde(default)]
+    workflow: Vec<workflow::WorkflowState>,
+    #[serde(default)]
+    views: Vec<views::SavedView>,
+    #[serde(skip)]
+    transaction: Option<undo::Transaction>,
 }
 impl Clone for KanbanDocument {
     fn clone(&self) -> Self {
@@ -42,6 +74,8 @@ impl Clone for KanbanDocument {
         self.tasks = source.tasks.clone();
         self.categories = source.categories.clone();
         self.priorities = source.priorities.clone();
+        self.workflow = source.workflow.clone();
+        self.views = source.views.clone();
         *self.next_id.write() = *source.next_id.read();
     }
 }
@@ -56,6 +90,9 @@ impl KanbanDocument {
             ]),
             categories: HashMap::new(),
             next_id: RwLock::new(0),
+            workflow: Vec::new(),
+            views: Vec::new(),
+            transaction: None,
         }
     }
     /** Determine if the child can be added to the parent's dependency list without
@@ -122,15 +159,23 @@ impl KanbanDocument {
     pub fn get_new_task_mut(&mut self) -> &mut KanbanItem {
         let new_task = KanbanItem::new(self);
         let new_task_id = new_task.id;
+        self.record_new_task(&new_task);
         self.tasks.insert(new_task_id, new_task);
         return self.tasks.get_mut(&new_task_id).unwrap();
     }
     pub fn get_new_task(&mut self) -> KanbanItem {
         let new_task = KanbanItem::new(self);
         let new_task_id = new_task.id;
+        self.record_new_task(&new_task);
         self.tasks.insert(new_task_id, new_task);
         return self.tasks.get(&new_task_id).unwrap().clone();
     }
+    fn record_new_task(&mut self, new_task: &KanbanItem) {
+        self.record_change(UndoItem::Create(undo::CreationEvent {
+            parent_id: None,
+            new_task: new_task.clone(),
+        }));
+    }
     pub fn get_tasks(&'_ self) -> Values<'_, KanbanId, KanbanItem> {
         self.tasks.values()
     }
@@ -172,7 +217,7 @@ impl KanbanDocument {
                 KanbanCategoryStyle::default(),
             );
         }
-        result
+        self.record_change(result)
     }
     pub fn get_sorted_priorities<'a>(&'a self) -> Vec<(&'a String, &'a i32)> {
         let mut i: Vec<(&'a String, &'a i32)> = self.priorities.iter().collect();
@@ -190,10 +235,10 @@ impl KanbanDocument {
             }
         }
         self.tasks.remove(&item.id);
-        undo::UndoItem::Delete(DeletionEvent {
+        self.record_change(undo::UndoItem::Delete(DeletionEvent {
             parent_ids: result,
             former_item: item.clone(),
-        })
+        }))
     }
     pub fn get_relation(&self, target: KanbanId, other: KanbanId) -> TaskRelation {
         if target == other {
@@ -258,10 +303,41 @@ impl KanbanDocument {
 }
 /// Category functions
 impl KanbanDocument {
-    pub fn replace_category_style(&mut self, name: &str, style: KanbanCategoryStyle) {
-        self.categories.insert(name.into(), style);
+    pub fn replace_category_style(&mut self, name: &str, style: KanbanCategoryStyle) -> UndoItem {
+        let former = self.categories.insert(name.into(), style);
+        self.record_change(UndoItem::Category(undo::CategoryEvent {
+            name: name.into(),
+            former_style: former,
+        }))
+    }
+    /// Forget a category's style. Tasks in the category keep it, and are shown unstyled.
+    pub fn remove_category_style(&mut self, name: &str) -> UndoItem {
+        let former = self.categories.remove(name);
+        self.record_change(UndoItem::Category(undo::CategoryEvent {
+            name: name.into(),
+            former_style: former,
+        }))
+    }
+    pub fn replace_priority(&mut self, name: &str, value: i32) -> UndoItem {
+        let former = self.priorities.insert(name.into(), value);
+        self.record_change(UndoItem::Priority(undo::PriorityEvent {
+            name: name.into(),
+            former_value: former,
+            time: Utc::now(),
+        }))
+    }
+    /// Forget a priority. Tasks with the priority keep it, and are treated as having a value
+    /// of zero.
+    pub fn remove_priority(&mut self, name: &str) -> UndoItem {
+        let former = self.priorities.remove(name);
+        self.record_change(UndoItem::Priority(undo::PriorityEvent {
+            name: name.into(),
+            former_value: former,
+            time: Utc::now(),
+        }))
     }
 }
+#[cfg(feature = "gui")]
 pub mod layout_cache {
     use super::*;
     use std::{borrow::Borrow, cell::RefCell};
@@ -290,6 +366,7 @@ pub mod layout_cache {
         });
     }
 }
+#[cfg(feature = "gui")]
 impl KanbanDocument {
     //! Produce a vertical layout scrolling downwards.
     //!
@@ -305,6 +382,67 @@ impl KanbanDocument {
         hovered_task: &mut Option<i32>,
         event_collector: &mut Vec<SummaryAction>,
         id_salt: impl std::hash::Hash,
+    ) -> f64 {
+        self.layout_rows(ui, ids, id_salt, |ui, item| {
+            event_collector.push(item.summary(self, hovered_task, ui));
+        })
+    }
+    /// Like [`Self::layout_id_list`], but the cards can be dragged, and other cards can be
+    /// dropped onto them to become their parent.
+    ///
+    /// Drops that would create a cycle are outlined in red and refused.
+    pub fn layout_draggable_id_list(
+        &self,
+        ui: &mut egui::Ui,
+        ids: &[KanbanId],
+        hovered_task: &mut Option<i32>,
+        event_collector: &mut Vec<SummaryAction>,
+        id_salt: impl std::hash::Hash,
+    ) -> f64 {
+        self.layout_rows(ui, ids, id_salt, |ui, item| {
+            let mut action = SummaryAction::NoAction;
+            let response = ui
+                .dnd_drag_source(egui::Id::new(("Card", item.id)), item.id, |ui| {
+                    action = item.summary(self, hovered_task, ui);
+                })
+                .response;
+            event_collector.push(action);
+            let Some(dragged) = response.dnd_hover_payload::<KanbanId>() else {
+                return;
+            };
+            if *dragged == item.id {
+                return;
+            }
+            let allowed = self.can_add_as_child(&self.tasks[&dragged], item);
+            ui.painter().rect_stroke(
+                response.rect,
+                ui.visuals().noninteractive().rounding,
+                Stroke::new(
+                    3.0,
+                    if allowed {
+                        Color32::from_rgb(0, 255, 0)
+                    } else {
+                        Color32::from_rgb(255, 0, 0)
+                    },
+                ),
+            );
+            ui.ctx().set_cursor_icon(if allowed {
+                egui::CursorIcon::PointingHand
+            } else {
+                egui::CursorIcon::NoDrop
+            });
+            // Taking the payload, even when refusing it, keeps the column from moving the task
+            if response.dnd_release_payload::<KanbanId>().is_some() && allowed {
+                event_collector.push(SummaryAction::AddChildTo(*dragged, item.id));
+            }
+        })
+    }
+    fn layout_rows(
+        &self,
+        ui: &mut egui::Ui,
+        ids: &[KanbanId],
+        id_salt: impl std::hash::Hash,
+        mut show_item: impl FnMut(&mut egui::Ui, &KanbanItem),
     ) -> f64 {
         let cache_key = egui::Id::new(&id_salt);
         egui::ScrollArea::vertical().id_salt(id_salt).show_rows(
@@ -315,10 +453,7 @@ impl KanbanDocument {
                 ui.vertical_centered_justified(|ui| {
                     for row in range.clone() {
                         let start = ui.cursor().min.y;
-                        let item_id = ids[row];
-                        let item = &self.tasks[&item_id];
-                        let action = item.summary(self, hovered_task, ui);
-                        event_collector.push(action);
+                        show_item(ui, &self.tasks[&ids[row]]);
                         let end = ui.cursor().min.y;
                         layout_cache::record_measurement(cache_key, (end - start) as f64);
                     }
@@ -347,6 +482,21 @@ pub struct KanbanItem {
     pub child_tasks: BTreeSet<KanbanId>,
     #[serde(default)]
     pub time_records: TimeRecords,
+    /// The workflow state the task is in, see [`workflow`]
+    #[serde(default)]
+    pub state: Option<String>,
+    /// When the task should be completed by, see [`schedule`]
+    #[serde(default)]
+    pub due: Option<DateTime<Utc>>,
+    /// The task isn't offered in the queue until this date
+    #[serde(default)]
+    pub start_after: Option<DateTime<Utc>>,
+    /// Create the task again when it's completed, see [`recurrence`]
+    #[serde(default)]
+    pub recurrence: Option<recurrence::Recurrence>,
+    /// How long the task is expected to take, see [`estimate`]
+    #[serde(default)]
+    pub estimate: Option<chrono::TimeDelta>,
 }
 impl KanbanItem {
     pub fn new(document: &KanbanDocument) -> Self {
@@ -360,6 +510,11 @@ impl KanbanItem {
             priority: None,
             child_tasks: BTreeSet::new(),
             time_records: Default::default(),
+            state: None,
+            due: None,
+            start_after: None,
+            recurrence: None,
+            estimate: None,
         }
     }
 
@@ -466,14 +621,29 @@ pub enum SummaryAction {
     MarkCompleted(KanbanId),
     FocusOn(KanbanId),
     AddChildTo(KanbanId, KanbanId),
+    /// Move the task into the column at the index, as in [`KanbanDocument::columns`]
+    MoveToColumn(KanbanId, usize),
+    /// Start timing the task, or stop its timer if it's running
+    ToggleTimer(KanbanId),
     UpdateLayout,
 }
+#[cfg(feature = "gui")]
 impl KanbanItem {
     pub fn summary(
         &self,
         document: &KanbanDocument,
         hovered_task: &mut Option<KanbanId>,
         ui: &mut egui::Ui,
+    ) -> SummaryAction {
+        self.highlighted_summary(document, hovered_task, ui, None)
+    }
+    /// Like [`Self::summary`], with where a search matched the name and description highlighted.
+    pub fn highlighted_summary(
+        &self,
+        document: &KanbanDocument,
+        hovered_task: &mut Option<KanbanId>,
+        ui: &mut egui::Ui,
+        highlights: Option<&search::Highlights>,
     ) -> SummaryAction {
         let mut action = SummaryAction::NoAction;
         let style = ui.visuals_mut();
@@ -498,6 +668,13 @@ impl KanbanItem {
             }
             _ => (),
         }
+        let overdue = self.is_overdue(Utc::now());
+        if overdue {
+            stroke = Stroke::new(
+                stroke.width.max(2.0),
+                Color32::from_rgba_unmultiplied(255, 140, 0, 255),
+            );
+        }
 
         if let Some(ht) = hovered_task {
             match document.get_relation(self.id, *ht) {
@@ -527,7 +704,11 @@ impl KanbanItem {
             ui.vertical(|ui| {
                 let mut label: Option<Response> = None;
                 ui.horizontal(|ui| {
-                    if hovered_task.is_none() {
+                    if let (None, Some(highlights)) = (&hovered_task, highlights) {
+                        let indices = highlights.in_field(search::Field::Name);
+                        label =
+                            Some(ui.label(search::highlight(ui, &self.name, indices, name_color)));
+                    } else if hovered_task.is_none() {
                         label = Some(ui.label(RichText::new(self.name.clone()).color(name_color)));
                     } else {
                         label = Some(ui.label(
@@ -573,6 +754,15 @@ impl KanbanItem {
                         action = SummaryAction::FocusOn(self.id);
                         ui.close_menu();
                     }
+                    let (timer_icon, timer_hint) = if self.time_records.is_recording() {
+                        ("⏹", "Stop the timer")
+                    } else {
+                        ("⏵", "Start timing this task, stopping any other timer")
+                    };
+                    if ui.button(timer_icon).on_hover_text(timer_hint).clicked() {
+                        action = SummaryAction::ToggleTimer(self.id);
+                        ui.close_menu();
+                    }
                 });
                 ui.horizontal(|ui| {
                     let thing = match self.completed {
@@ -582,11 +772,59 @@ impl KanbanItem {
                         None => "Not completed".into(),
                     };
                     ui.label(RichText::new(thing).color(status_color).strong());
+                    // The columns no longer say whether the task is blocked
+                    if !document.workflow().is_empty()
+                        && document.task_status(&self.id) == Status::Blocked
+                    {
+                        ui.label(
+                            RichText::new("Blocked")
+                                .color(Color32::WHITE)
+                                .background_color(Color32::from_rgba_unmultiplied(200, 0, 0, 255)),
+                        )
+                        .on_hover_text("Waiting on child tasks to be completed");
+                    }
+                    if let Some(recurrence) = &self.recurrence {
+                        ui.label("↻").on_hover_text(recurrence.to_string());
+                    }
                 });
+                if let (Some(due), None) = (self.due, self.completed) {
+                    let due = schedule::format_date(&due);
+                    ui.label(if overdue {
+                        RichText::new(format!("Overdue since {}", due))
+                            .color(Color32::BLACK)
+                            .background_color(Color32::from_rgba_unmultiplied(255, 140, 0, 255))
+                            .strong()
+                    } else {
+                        RichText::new(format!("Due {}", due))
+                    });
+                }
+                let rollup = document.rollup(self.id);
+                if !rollup.estimated.is_zero() {
+                    let mut hint = format!(
+                        "Estimated {}, {} spent",
+                        estimate::format_estimate(&rollup.estimated),
+                        estimate::format_estimate(&rollup.spent)
+                    );
+                    if rollup.unestimated > 0 {
+                        hint += &format!(", {} tasks aren't estimated", rollup.unestimated);
+                    }
+                    ui.label(format!(
+                        "⌛ {} left",
+                        estimate::format_estimate(&rollup.remaining)
+                    ))
+                    .on_hover_text(hint);
+                }
                 ScrollArea::vertical()
                     .id_salt(format!("Summary for item {}", self.id))
                     .max_height(50.0)
-                    .show(ui, |ui| ui.label(RichText::new(self.description.clone())));
+                    .show(ui, |ui| match highlights {
+                        Some(highlights) => {
+                            let indices = highlights.in_field(search::Field::Description);
+                            let color = ui.visuals().text_color();
+                            ui.label(search::highlight(ui, &self.description, indices, color))
+                        }
+                        None => ui.label(RichText::new(self.description.clone())),
+                    });
                 // if ui.min_size().y < 200. {
                 //     ui.allocate_space(Vec2::new(ui.available_width(), 200. - ui.min_size().y));
                 // }
@@ -618,92 +856,18 @@ impl KanbanItem {
         false
     }
 }
-/*
-*/
-pub mod search {
-    use nucleo_matcher::{pattern::Pattern, Config, Utf32Str};
-
-    use super::KanbanId;
-
-    #[derive(Clone, Default)]
-    pub struct SearchState {
-        pub matched_ids: Vec<i32>,
-        pub search_prompt: String,
-        /**
-        The former search prompt, if search_prompt and former_search_prompt are in disagreement
-        the matched_ids must be rebuilt.
-        */
-        former_search_prompt: String,
-        matcher: nucleo_matcher::Matcher,
-        pattern: nucleo_matcher::pattern::Pattern,
-    }
-
-    impl SearchState {
-        pub fn new() -> Self {
-            SearchState {
-                matched_ids: Vec::new(),
-                search_prompt: String::new(),
-                former_search_prompt: String::new(),
-                matcher: nucleo_matcher::Matcher::new(Config::DEFAULT),
-                pattern: Pattern::new(
-                    "",
-                    nucleo_matcher::pattern::CaseMatching::Smart,
-                    nucleo_matcher::pattern::Normalization::Smart,
-                    nucleo_matcher::pattern::AtomKind::Fuzzy,
-                ),
-            }
-        }
-        pub fn force_update(&mut self) {
-            self.matched_ids.clear();
-        }
-        pub fn update(&mut self, document: &super::KanbanDocument) {
-            // This is *kinda* expensive, so we should avoid it if possible.
-            // The two conditions I can think of off the top of my head are that
-            // if the search prompt is unchanged, and the matched_ids are not empty, then
-            // we don't need to update.
-            if self.search_prompt == self.former_search_prompt && !self.matched_ids.is_empty() {
-                return;
-            }
-            if self.search_prompt != self.former_search_prompt {
-                self.pattern.reparse(
-                    &self.search_prompt,
-                    nucleo_matcher::pattern::CaseMatching::Smart,
-                    nucleo_matcher::pattern::Normalization::Smart,
-                );
-                self.former_search_prompt = self.search_prompt.clone();
-            }
-            self.matched_ids.clear();
-            let mut thing: String = "".into();
-            let mut utfs_buffer: Vec<char> = Vec::new();
-            let mut values: Vec<(KanbanId, i32)> = Vec::new();
-            for i in document.get_tasks() {
-                thing.clear();
-                i.fill_searchable_buffer(&mut thing);
-
-                if let Some(score) = self.pattern.score(
-                    Utf32Str::new(thing.as_str(), &mut utfs_buffer),
-                    &mut self.matcher,
-                ) {
-                    values.push((i.id, score as i32));
-                }
-            }
-            values.sort_by_key(|x| x.1);
-            // println!("Top score: {}", values.first().unwrap().1);
-            // values.reverse();
-            self.matched_ids.extend(values.drain(..).map(|x| x.0));
-            self.matched_ids.reverse();
-            self.former_search_prompt = self.search_prompt.clone();
-        }
-    }
-}
 /**
  module for the queue_view, in this case, the cache state.
 */
 pub mod queue_view {
     use super::*;
+    use filter::KanbanFilter;
+    use sorting::ItemSort;
     #[derive(PartialEq, Eq, Clone)]
     pub struct QueueState {
         pub cached_ready: Vec<KanbanId>,
+        /// The ready tasks that are nearly done, smallest first, see [`estimate::SMALL_TASK`]
+        pub quick_wins: Vec<KanbanId>,
     }
     impl Default for QueueState {
         fn default() -> Self {
@@ -714,16 +878,43 @@ pub mod queue_view {
         pub fn new() -> Self {
             QueueState {
                 cached_ready: Vec::new(),
+                quick_wins: Vec::new(),
             }
         }
-        pub fn update(&mut self, document: &KanbanDocument) {
-            let thing = document.get_tasks().map(|x| x.id);
+        /// Find the ready tasks the filter matches, most pressing first. The sort only decides
+        /// between tasks that are as pressing as each other.
+        pub fn update(
+            &mut self,
+            document: &KanbanDocument,
+            filter: &KanbanFilter,
+            sort: &ItemSort,
+        ) {
+            let now = Utc::now();
+            let thing = document
+                .get_tasks()
+                .filter(|x| x.has_started(now))
+                .filter(|x| filter.matches(x, document))
+                .map(|x| x.id);
             self.cached_ready.clear();
             self.cached_ready
                 .extend(thing.filter(|x| document.task_status(x) == Status::Ready));
-            self.cached_ready
-                .sort_by_key(|x| document.task_priority_value(x));
-            self.cached_ready.reverse();
+            self.cached_ready.sort_by(|a, b| {
+                document
+                    .queue_score(b, now)
+                    .cmp(&document.queue_score(a, now))
+                    .then_with(|| sort.cmp_by(&document.tasks[a], &document.tasks[b], document))
+            });
+            self.quick_wins.clear();
+            self.quick_wins
+                .extend(self.cached_ready.iter().copied().filter(|x| {
+                    document.tasks[x]
+                        .remaining_estimate()
+                        .is_some_and(|x| x <= estimate::SMALL_TASK)
+                }));
+            // Stable, so tasks left the same are still in the queue's order
+            self.quick_wins
+                .sort_by_key(|x| document.tasks[x].remaining_estimate());
+            self.quick_wins.truncate(3);
         }
     }
 }
@@ -732,9 +923,42 @@ pub mod queue_view {
  * in order to avoid applying the changes instantaneously and making it uncomfortably
  * 'twitchy'
 */
+#[cfg(feature = "gui")]
 pub mod editor;
+/// Helpers for tests, both the library's and the application's, which can't see anything
+/// compiled only for the library's own tests.
+#[doc(hidden)]
+pub mod testing {
+    use super::*;
+    /**
+    Make a KanbanDocument easily.
+
+    * `number_of_tasks` - The number of tasks to populate the document with.
+    * `children` - The ids of each child in order. If the vector ends prior to the last task,
+      it assumes none of the following tasks have children. Assume that ids start from 0 and end at number_of_tasks
+    */
+    pub fn make_document_easy(
+        number_of_tasks: usize,
+        children: &[Vec<KanbanId>],
+    ) -> KanbanDocument {
+        let mut n = KanbanDocument::new();
+        let mut ids = Vec::new();
+        for _ in 0..number_of_tasks {
+            ids.push(n.get_new_task_mut().id);
+        }
+        for (index, child_set) in ids.iter().zip(children.iter()) {
+            let mut task = n.get_task(*index).unwrap().clone();
+            for child_id in child_set.iter() {
+                task.child_tasks.insert(*child_id);
+            }
+            n.replace_task(&task);
+        }
+        n
+    }
+}
 #[cfg(test)]
 pub mod tests {
+    pub use super::testing::make_document_easy;
     use super::*;
     #[test]
     fn test_cycle_detection() {
@@ -776,31 +1000,6 @@ pub mod tests {
             assert!(copy.child_tasks.is_empty());
         }
     }
-    /**
-    Make a KanbanDocument easily.
-
-    * `number_of_tasks` - The number of tasks to populate the document with.
-    * `children` - The ids of each child in order. If the vector ends prior to the last task,
-      it assumes none of the following tasks have children. Assume that ids start from 0 and end at number_of_tasks
-    */
-    pub fn make_document_easy(
-        number_of_tasks: usize,
-        children: &[Vec<KanbanId>],
-    ) -> KanbanDocument {
-        let mut n = KanbanDocument::new();
-        let mut ids = Vec::new();
-        for _ in 0..number_of_tasks {
-            ids.push(n.get_new_task_mut().id);
-        }
-        for (index, child_set) in ids.iter().zip(children.iter()) {
-            let mut task = n.get_task(*index).unwrap().clone();
-            for child_id in child_set.iter() {
-                task.child_tasks.insert(*child_id);
-            }
-            n.replace_task(&task);
-        }
-        n
-    }
     #[test]
     fn test_inheritance() {
         let mut document = KanbanDocument::new();
@@ -858,7 +1057,11 @@ pub mod tests {
             document.replace_task(&task);
             assert_eq!(document.get_task(1).unwrap().child_tasks.len(), 1);
             let mut qs = QueueState::new();
-            qs.update(&document);
+            qs.update(
+                &document,
+                &filter::KanbanFilter::None,
+                &sorting::ItemSort::default(),
+            );
             assert_eq!(qs.cached_ready.len(), 2);
             assert!(qs.cached_ready.contains(&0));
             assert!(qs.cached_ready.contains(&3));
@@ -870,10 +1073,59 @@ pub mod tests {
             {
                 assert_eq!(document.task_status(&task.id), Status::Ready);
             }
+            assert!(qs.quick_wins.is_empty());
+            // Only small ready tasks are suggested
+            for (id, minutes) in [(0, 20), (3, 90), (1, 10)] {
+                let mut task = document.get_task(id).unwrap().clone();
+                task.estimate = Some(chrono::TimeDelta::minutes(minutes));
+                document.replace_task(&task);
+            }
+            qs.update(
+                &document,
+                &filter::KanbanFilter::None,
+                &sorting::ItemSort::default(),
+            );
+            assert_eq!(qs.quick_wins, vec![0]);
+            // Tasks as pressing as each other are put in order by the sort
+            let newest =
+                sorting::ItemSort::by(sorting::SortKey::Id, sorting::Direction::Descending);
+            qs.update(&document, &filter::KanbanFilter::None, &newest);
+            assert_eq!(qs.cached_ready, vec![3, 0]);
+            qs.update(
+                &document,
+                &filter::KanbanFilter::query("estimate<1h"),
+                &newest,
+            );
+            assert_eq!(qs.cached_ready, vec![0]);
+        }
+    }
+    #[test]
+    fn test_search_order() {
+        let mut document = make_document_easy(3, &[]);
+        for (id, name) in [
+            (0, "Fix part of the server"),
+            (1, "Parser"),
+            (2, "Write docs"),
+        ] {
+            let mut task = document.get_task(id).unwrap().clone();
+            task.name = name.into();
+            document.replace_task(&task);
         }
+        let oldest = sorting::ItemSort::by(sorting::SortKey::Id, sorting::Direction::Ascending);
+        let mut search = search::SearchState::new();
+        search.search_prompt = "parser".into();
+        search.update(&document, &filter::KanbanFilter::None, &oldest);
+        assert_eq!(search.matched_ids, vec![1, 0]);
+        search.sort_first = true;
+        search.force_update();
+        search.update(&document, &filter::KanbanFilter::None, &oldest);
+        assert_eq!(search.matched_ids, vec![0, 1]);
+        search.force_update();
+        search.update(&document, &filter::KanbanFilter::query("name:fix"), &oldest);
+        assert_eq!(search.matched_ids, vec![0]);
     }
 }
-#[derive(Serialize, Deserialize, Default, PartialEq, Copy, Clone)]
+#[derive(Serialize, Deserialize, Default, PartialEq, Copy, Clone, Debug)]
 pub struct KanbanCategoryStyle {
     pub panel_stroke_width: Option<f32>,
     pub panel_stroke_color: Option<[u8; 4]>,
@@ -882,6 +1134,7 @@ pub struct KanbanCategoryStyle {
     #[serde(default)]
     pub children_inherit_category: bool,
 }
+#[cfg(feature = "gui")]
 impl KanbanCategoryStyle {
     pub fn apply_to(
         &self,
@@ -275,7 +275,7 @@ impl NodeLayout {
         let mut handles: BTreeMap<KanbanId, NodeHandle> = BTreeMap::new();
         let mut arrow = Arrow::simple("");
         arrow.end = LineEndKind::Arrow;
-        let tasks: Vec<&KanbanItem> = if let Some(focused_id) = self.focus {
+        let mut tasks: Vec<&KanbanItem> = if let Some(focused_id) = self.focus {
             document
                 .get_tasks()
                 .filter(|x| {
@@ -311,6 +311,8 @@ impl NodeLayout {
             //     add_item_to_graph(i, document, style, &mut vg, &mut handles);
             // }
         };
+        // Nodes are added in sorted order, as their edges are below
+        tasks.sort_by(|a, b| sort.cmp_by(a, b, document));
         tasks
             .iter()
             .for_each(|x| add_item_to_graph(x, document, style, &mut vg, &mut handles));
@@ -498,6 +500,26 @@ impl NodeLayout {
     pub fn set_focus(&mut self, id: &KanbanId) {
         self.focus = Some(*id);
     }
+    /// The layout's settings, for saving as a view.
+    pub fn preset(&self) -> views::LayoutPreset {
+        views::LayoutPreset::Node {
+            focus: self.focus,
+            exclude_completed: self.exclude_completed,
+            collapsed: self.collapsed.clone(),
+        }
+    }
+    pub fn from_preset(
+        focus: Option<KanbanId>,
+        exclude_completed: bool,
+        collapsed: Vec<KanbanId>,
+    ) -> Self {
+        NodeLayout {
+            focus,
+            exclude_completed,
+            collapsed,
+            ..Self::new()
+        }
+    }
 }
 
 fn add_item_to_graph<G>(
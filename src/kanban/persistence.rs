@@ -0,0 +1,329 @@
+//! Reading and writing documents to and from disk.
+//!
+//! Documents are saved inside an envelope recording the version of the file format they were
+//! written with. Older documents are upgraded one version at a time by [`MIGRATIONS`] on load,
+//! and documents written by a newer build are refused rather than risk dropping fields this
+//! build doesn't know about.
+use super::{integrity::Diagnostic, KanbanDocument};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fs, io, path::Path};
+
+/// The version of the file format written by this build.
+///
+/// Fields added to the document are read with their defaults from documents that lack them, so
+/// the version only changes when existing data has to be transformed to be read.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Upgrades a document's contents from the version matching its index to the next one.
+type Migration = fn(&mut Value);
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    // Version 0 documents are the bare document without an envelope, the contents are unchanged.
+    |_| {},
+    // Version 2 sorts saved views by several keys in either direction, rather than by one.
+    |document| {
+        let views = document.get_mut("views").and_then(Value::as_array_mut);
+        for view in views.into_iter().flatten() {
+            if let Some(sort) = view.get_mut("sort") {
+                let key = match sort.as_str() {
+                    Some("Id") => Some(("Id", "Ascending")),
+                    Some("Newest") => Some(("Id", "Descending")),
+                    Some(key @ ("Name" | "Category" | "Completed")) => Some((key, "Ascending")),
+                    _ => None,
+                };
+                *sort = serde_json::json!({ "keys": key.map_or(vec![], |x| vec![x]) });
+            }
+        }
+    },
+];
+
+/// Why a document couldn't be loaded or saved.
+#[derive(Debug)]
+pub enum DocumentError {
+    /// The file couldn't be read or written.
+    Io(io::Error),
+    /// The file isn't a valid document. Documents whose contents had to be changed to upgrade
+    /// them report errors in their contents at line 0, as they are no longer being read from the
+    /// file at that point.
+    Parse {
+        line: usize,
+        column: usize,
+        error: serde_json::Error,
+    },
+    /// The document was written by a newer version of kanbanrs.
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The document was read, but contradicts itself. It can still be opened after being
+    /// repaired with [`KanbanDocument::repair`].
+    Integrity {
+        problems: Vec<Diagnostic>,
+        document: Box<KanbanDocument>,
+    },
+}
+impl std::fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(x) => write!(f, "{}", x),
+            Self::Parse { error, .. } => write!(f, "Not a valid document: {}", error),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "The document was written by a newer version of kanbanrs (format version {}, \
+                 this build understands up to {}). Open it with a newer build so nothing is lost.",
+                found, supported
+            ),
+            Self::Integrity { problems, .. } => {
+                write!(f, "The document is damaged:")?;
+                for problem in problems.iter() {
+                    write!(f, "\n * {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for DocumentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(x) => Some(x),
+            Self::Parse { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for DocumentError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<serde_json::Error> for DocumentError {
+    fn from(value: serde_json::Error) -> Self {
+        if value.is_io() {
+            Self::Io(value.into())
+        } else {
+            Self::Parse {
+                line: value.line(),
+                column: value.column(),
+                error: value,
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    format_version: u32,
+    document: &'a KanbanDocument,
+}
+#[derive(Deserialize)]
+struct OwnedEnvelope<T> {
+    document: T,
+}
+/// Only the version, to decide how the rest should be read.
+#[derive(Deserialize)]
+struct VersionProbe {
+    format_version: Option<u32>,
+}
+
+/// Read a document from `path`.
+pub fn open(path: &Path) -> Result<KanbanDocument, DocumentError> {
+    parse(&fs::read_to_string(path)?)
+}
+/// Read a document whose contents are already in the current format, with or without an
+/// envelope depending on its version.
+fn read_directly(text: &str, version: u32) -> Result<KanbanDocument, serde_json::Error> {
+    if version == 0 {
+        serde_json::from_str(text)
+    } else {
+        serde_json::from_str::<OwnedEnvelope<KanbanDocument>>(text).map(|x| x.document)
+    }
+}
+/// Read a document from its saved form, upgrading it if it was written by an older version.
+pub fn parse(text: &str) -> Result<KanbanDocument, DocumentError> {
+    let version = serde_json::from_str::<VersionProbe>(text)?
+        .format_version
+        .unwrap_or(0);
+    if version > FORMAT_VERSION {
+        return Err(DocumentError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    let mut document = if version == FORMAT_VERSION {
+        // Deserializing directly keeps the line and column of any error.
+        read_directly(text, version)?
+    } else {
+        let contents: Value = if version == 0 {
+            serde_json::from_str(text)?
+        } else {
+            serde_json::from_str::<OwnedEnvelope<Value>>(text)?.document
+        };
+        let mut upgraded = contents.clone();
+        for migration in MIGRATIONS[version as usize..].iter() {
+            migration(&mut upgraded);
+        }
+        // Most older documents need nothing changing, and can be read directly as well
+        if upgraded == contents {
+            read_directly(text, version)?
+        } else {
+            serde_json::from_value(upgraded)?
+        }
+    };
+    let problems = document.validate();
+    if problems.iter().any(Diagnostic::is_damage) {
+        return Err(DocumentError::Integrity {
+            problems,
+            document: Box::new(document),
+        });
+    }
+    // Priorities removed while tasks still had them are left alone, the check in the Edit menu
+    // still points them out
+    for problem in problems.iter() {
+        if let Diagnostic::NextIdBehind { .. } = problem {
+            document.fix(problem);
+        }
+    }
+    Ok(document)
+}
+
+/// Write the document to `path`.
+///
+/// The document is first written to a temporary file alongside the target, which is then
+/// renamed over it, so a failure partway through never leaves a truncated document behind.
+pub fn save(path: &Path, document: &KanbanDocument) -> Result<(), DocumentError> {
+    let mut tmp_path = path.to_path_buf();
+    tmp_path.set_extension("kan.bak");
+    let file = fs::File::create(&tmp_path)?;
+    let mut writer = io::BufWriter::new(file);
+    serde_json::to_writer(
+        &mut writer,
+        &Envelope {
+            format_version: FORMAT_VERSION,
+            document,
+        },
+    )?;
+    io::Write::flush(&mut writer)?;
+    drop(writer);
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kanban::sorting::{Direction, ItemSort, SortKey};
+    #[test]
+    fn test_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("kanbanrs-roundtrip-{}.kan", std::process::id()));
+        let mut document = KanbanDocument::new();
+        let mut task = document.get_new_task();
+        task.name = "Saved".into();
+        document.replace_task(&task);
+        save(&path, &document).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.starts_with(&format!("{{\"format_version\":{}", FORMAT_VERSION)));
+        let loaded = parse(&text).unwrap();
+        assert_eq!(loaded.get_task(task.id).unwrap().name, "Saved");
+        // The next id must survive the trip, otherwise new tasks would collide with old ones.
+        assert_ne!(loaded.get_next_id(), task.id);
+    }
+    #[test]
+    fn test_unversioned_document() {
+        let document = parse(
+            r#"{"tasks":{"0":{"id":0,"name":"Old","description":"","completed":null,
+            "category":null,"priority":null,"tags":[],"child_tasks":[]}},
+            "priorities":{},"categories":{},"next_id":1}"#,
+        )
+        .unwrap();
+        assert_eq!(document.get_task(0).unwrap().name, "Old");
+    }
+    #[test]
+    fn test_single_key_sort_is_upgraded() {
+        let document = parse(
+            r#"{"format_version":1,"document":{"tasks":{},"priorities":{},"categories":{},
+            "next_id":0,"views":[{"name":"Latest","filter":"None","sort":"Newest",
+            "layout":"Queue"},{"name":"Unsorted","filter":"None","sort":"None","layout":"Queue"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            document.views()[0].sort,
+            ItemSort::by(SortKey::Id, Direction::Descending)
+        );
+        assert_eq!(document.views()[1].sort, ItemSort::default());
+    }
+    #[test]
+    fn test_newer_version_is_refused() {
+        let text = format!(
+            r#"{{"format_version":{},"document":{{}}}}"#,
+            FORMAT_VERSION + 1
+        );
+        assert!(matches!(
+            parse(&text),
+            Err(DocumentError::UnsupportedVersion { found, .. }) if found == FORMAT_VERSION + 1
+        ));
+    }
+    #[test]
+    fn test_parse_error_location() {
+        let text = format!(
+            "{{\"format_version\":{},\n\"document\":{{\"tasks\":[}}",
+            FORMAT_VERSION
+        );
+        match parse(&text) {
+            Err(DocumentError::Parse { line, column, .. }) => {
+                assert_eq!(line, 2);
+                assert!(column > 0);
+            }
+            _ => panic!("A truncated document should not parse"),
+        }
+        // Documents from before the envelope still point at where the problem is
+        match parse("{\"tasks\":{},\n\"priorities\":[],\"categories\":{},\"next_id\":0}") {
+            Err(DocumentError::Parse { line, column, .. }) => {
+                assert_eq!(line, 2);
+                assert!(column > 0);
+            }
+            _ => panic!("Priorities should be a map"),
+        }
+        assert!(matches!(
+            open(Path::new("/this/does/not/exist.kan")),
+            Err(DocumentError::Io(_))
+        ));
+    }
+    #[test]
+    fn test_dangling_child_is_refused() {
+        let mut document = KanbanDocument::new();
+        let mut task = document.get_new_task();
+        task.child_tasks.insert(42);
+        document.replace_task(&task);
+        let text = serde_json::to_string(&Envelope {
+            format_version: FORMAT_VERSION,
+            document: &document,
+        })
+        .unwrap();
+        match parse(&text) {
+            Err(DocumentError::Integrity {
+                problems,
+                mut document,
+            }) => {
+                assert_eq!(problems.len(), 1);
+                document.repair();
+                assert!(document.get_task(task.id).unwrap().child_tasks.is_empty());
+            }
+            _ => panic!("A dangling child should be reported"),
+        }
+    }
+    #[test]
+    fn test_removed_priority_still_loads() {
+        let mut document = KanbanDocument::new();
+        let mut task = document.get_new_task();
+        task.priority = Some("High".into());
+        document.replace_task(&task);
+        document.remove_priority("High");
+        let text = serde_json::to_string(&Envelope {
+            format_version: FORMAT_VERSION,
+            document: &document,
+        })
+        .unwrap();
+        let loaded = parse(&text).unwrap();
+        assert_eq!(loaded.task_priority_value(&task.id), 0);
+        assert!(!loaded.validate().is_empty());
+    }
+}
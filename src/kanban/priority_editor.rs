@@ -4,6 +4,11 @@ pub struct PriorityEditor {
     pub current_value: i32,
     pub open: bool,
 }
+impl Default for PriorityEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl PriorityEditor {
     pub fn new() -> Self {
         PriorityEditor {
@@ -16,9 +21,9 @@ impl PriorityEditor {
     /// * `document` the document to operate on
     /// * `ui` The UI instance
     ///
-    /// returns true if the layout needs to be updated due to user action.
-    pub fn show(&mut self, document: &mut KanbanDocument, ui: &mut egui::Ui) -> bool {
-        let mut needs_change = false;
+    /// returns the changes made to the document, to be recorded for undoing.
+    pub fn show(&mut self, document: &mut KanbanDocument, ui: &mut egui::Ui) -> Vec<UndoItem> {
+        let mut changes = Vec::new();
         let mut items: Vec<(String, i32)> = document
             .priorities
             .iter()
@@ -37,12 +42,9 @@ impl PriorityEditor {
                 self.current_value = x;
             }
             if !self.name.is_empty() && ui.button("Add").clicked() {
-                document
-                    .priorities
-                    .insert(self.name.clone(), self.current_value);
+                changes.push(document.replace_priority(&self.name, self.current_value));
                 self.name.clear();
                 self.current_value = 0;
-                needs_change = true;
             }
         });
         ScrollArea::vertical().id_salt("priorities").show(ui, |ui| {
@@ -50,16 +52,14 @@ impl PriorityEditor {
                 ui.horizontal(|ui| {
                     ui.label(format!("{} - {}", name, priority));
                     if ui.button("+").clicked() {
-                        *document.priorities.get_mut(name).unwrap() += 1;
-                        needs_change = true;
+                        changes.push(document.replace_priority(name, priority + 1));
                     }
                     if ui.button("-").clicked() {
-                        *document.priorities.get_mut(name).unwrap() -= 1;
-                        needs_change = true;
+                        changes.push(document.replace_priority(name, priority - 1));
                     }
                 });
             }
         });
-        needs_change
+        changes
     }
 }
@@ -0,0 +1,488 @@
+//! A small language for filtering tasks, such as
+//! `category:bug AND NOT tag:wontfix AND priority>=Medium AND completed:none`.
+//!
+//! Terms are combined with `AND`, `OR` and `NOT`, grouped with parentheses, and terms written
+//! one after another must all match. A term is either a word or quoted phrase found anywhere in
+//! the task, or a field compared with a value:
+//!
+//! * `name:`, `desc:` contain the text
+//! * `cat:`, `tag:`, `state:` are the category, one of the tags or the workflow state, `cat:none`
+//!   and `state:none` for tasks without one
+//! * `prio:` compares the priority's value, `prio>=Medium`, or `prio:none`
+//! * `status:` is `ready`, `blocked` or `completed`
+//! * `completed`, `due` and `start` compare the day with a date like `2024-05-31` or `today`,
+//!   and `:none` or `:any` test whether there is one
+//! * `spent` and `estimate` compare durations like `90m` or `"1h 30m"`, quoted for the space
+//! * `related:` is the id of a task the task is a parent or child of
+//!
+//! Every field but `status` and `related` can be compared with `:`, `=` or `!=`, and those with
+//! an order with `<`, `<=`, `>` and `>=` as well. Text is matched without regard to case.
+use super::estimate::parse_estimate;
+use super::*;
+use chrono::{NaiveDate, TimeDelta};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+impl Comparison {
+    fn test<T: Ord>(self, value: T, against: T) -> bool {
+        match self {
+            Self::Less => value < against,
+            Self::LessOrEqual => value <= against,
+            Self::Equal => value == against,
+            Self::GreaterOrEqual => value >= against,
+            Self::Greater => value > against,
+        }
+    }
+}
+/// A test of a field the task might not have a value for.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Test<T> {
+    None,
+    Any,
+    Compare(Comparison, T),
+}
+impl<T: Ord> Test<T> {
+    fn test(&self, value: Option<T>) -> bool {
+        match (self, value) {
+            (Self::None, value) => value.is_none(),
+            (Self::Any, value) => value.is_some(),
+            (Self::Compare(comparison, against), Some(value)) => comparison.test(&value, against),
+            (Self::Compare(..), None) => false,
+        }
+    }
+}
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Query {
+    /// Every one matches, so an empty query matches every task
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    /// Found in any of the task's text
+    Text(String),
+    Name(String),
+    Description(String),
+    Category(Option<String>),
+    Tag(String),
+    State(Option<String>),
+    /// Compared by the priorities' values
+    Priority(Test<String>),
+    Status(Status),
+    Completed(Test<NaiveDate>),
+    Due(Test<NaiveDate>),
+    Starts(Test<NaiveDate>),
+    /// Tasks without any time logged have none
+    Spent(Test<TimeDelta>),
+    Estimate(Test<TimeDelta>),
+    RelatedTo(KanbanId),
+}
+/// Why a query couldn't be read, and where.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QueryError {
+    pub message: String,
+    /// The offset in bytes of the problem in the query
+    pub position: usize,
+}
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+impl std::error::Error for QueryError {}
+fn error<T>(message: impl Into<String>, position: usize) -> Result<T, QueryError> {
+    Err(QueryError {
+        message: message.into(),
+        position,
+    })
+}
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            next: 0,
+            end: text.len(),
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Query::And(Vec::new()));
+        }
+        let query = parser.parse_or()?;
+        match parser.tokens.get(parser.next) {
+            Some((position, _)) => error("Unexpected ')'", *position),
+            None => Ok(query),
+        }
+    }
+    pub fn matches(&self, item: &KanbanItem, document: &KanbanDocument) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(part);
+        let same = |text: Option<&String>, other: &Option<String>| match (text, other) {
+            (Some(text), Some(other)) => text.to_lowercase() == *other,
+            (None, None) => true,
+            _ => false,
+        };
+        let day = |x: Option<DateTime<Utc>>| x.map(|x| x.with_timezone(&Local).date_naive());
+        match self {
+            Self::And(terms) => terms.iter().all(|x| x.matches(item, document)),
+            Self::Or(terms) => terms.iter().any(|x| x.matches(item, document)),
+            Self::Not(term) => !term.matches(item, document),
+            Self::Text(text) => {
+                let mut buffer = String::new();
+                item.fill_searchable_buffer(&mut buffer);
+                contains(&buffer, text)
+            }
+            Self::Name(text) => contains(&item.name, text),
+            Self::Description(text) => contains(&item.description, text),
+            Self::Category(category) => same(item.category.as_ref(), category),
+            Self::Tag(tag) => item.tags.iter().any(|x| x.to_lowercase() == *tag),
+            Self::State(state) => same(item.state.as_ref(), state),
+            Self::Priority(test) => {
+                let value_of = |name: &String| {
+                    document
+                        .priorities
+                        .iter()
+                        .find(|(x, _)| x.to_lowercase() == name.to_lowercase())
+                        .map(|(_, value)| *value)
+                };
+                let value = item.priority.as_ref().map(|x| value_of(x).unwrap_or(0));
+                match test {
+                    Test::Compare(comparison, name) => match (value, value_of(name)) {
+                        (Some(value), Some(against)) => comparison.test(value, against),
+                        _ => false,
+                    },
+                    Test::None => value.is_none(),
+                    Test::Any => value.is_some(),
+                }
+            }
+            Self::Status(status) => document.task_status(&item.id) == *status,
+            Self::Completed(test) => test.test(day(item.completed)),
+            Self::Due(test) => test.test(day(item.due)),
+            Self::Starts(test) => test.test(day(item.start_after)),
+            Self::Spent(test) => {
+                let spent = item.time_records.duration();
+                test.test((!spent.is_zero()).then_some(spent))
+            }
+            Self::Estimate(test) => test.test(item.estimate),
+            Self::RelatedTo(id) => {
+                document.get_task(*id).is_some()
+                    && document.get_relation(*id, item.id) != TaskRelation::Unrelated
+            }
+        }
+    }
+    /// Problems with the query that depend on the document, such as priorities it doesn't have.
+    pub fn check(&self, document: &KanbanDocument) -> Result<(), String> {
+        match self {
+            Self::And(terms) | Self::Or(terms) => terms.iter().try_for_each(|x| x.check(document)),
+            Self::Not(term) => term.check(document),
+            Self::Priority(Test::Compare(_, name))
+                if !document
+                    .priorities
+                    .keys()
+                    .any(|x| x.to_lowercase() == name.to_lowercase()) =>
+            {
+                Err(format!("There's no priority named '{}'", name))
+            }
+            Self::RelatedTo(id) if document.get_task(*id).is_none() => {
+                Err(format!("There's no task {}", id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    /// The text, and where in it the first quote was, as nothing quoted is a keyword or a field
+    Word(String, Option<usize>),
+}
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut characters = text.char_indices().peekable();
+    while let Some((position, character)) = characters.next() {
+        match character {
+            x if x.is_whitespace() => continue,
+            '(' => tokens.push((position, Token::Open)),
+            ')' => tokens.push((position, Token::Close)),
+            _ => {
+                let mut word = String::new();
+                let mut first_quote = None;
+                let mut current = Some((position, character));
+                while let Some((position, character)) = current {
+                    if character == '"' {
+                        first_quote.get_or_insert(word.len());
+                        loop {
+                            match characters.next() {
+                                Some((_, '"')) => break,
+                                Some((_, x)) => word.push(x),
+                                None => return error("This quote is never closed", position),
+                            }
+                        }
+                    } else {
+                        word.push(character);
+                    }
+                    current =
+                        characters.next_if(|(_, x)| !x.is_whitespace() && !matches!(x, '(' | ')'));
+                }
+                tokens.push((position, Token::Word(word, first_quote)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Where the query ends, for errors about something missing at the end
+    end: usize,
+}
+impl Parser {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.next), Some((_, Token::Word(x, None))) if x == keyword)
+    }
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut terms = vec![self.parse_and()?];
+        while self.is_keyword("OR") {
+            self.next += 1;
+            terms.push(self.parse_and()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            Query::Or(terms)
+        })
+    }
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut terms = vec![self.parse_unary()?];
+        loop {
+            match self.tokens.get(self.next) {
+                None | Some((_, Token::Close)) => break,
+                _ if self.is_keyword("OR") => break,
+                _ if self.is_keyword("AND") => self.next += 1,
+                // Terms one after another must all match
+                _ => (),
+            }
+            terms.push(self.parse_unary()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            Query::And(terms)
+        })
+    }
+    fn parse_unary(&mut self) -> Result<Query, QueryError> {
+        let Some((position, token)) = self.tokens.get(self.next) else {
+            return error("Expected a term after this", self.end);
+        };
+        let position = *position;
+        self.next += 1;
+        match token {
+            Token::Open => {
+                let query = self.parse_or()?;
+                match self.tokens.get(self.next) {
+                    Some((_, Token::Close)) => {
+                        self.next += 1;
+                        Ok(query)
+                    }
+                    _ => error("This '(' is never closed", position),
+                }
+            }
+            Token::Close => error("Expected a term before ')'", position),
+            Token::Word(x, None) if x == "NOT" => Ok(Query::Not(Box::new(self.parse_unary()?))),
+            Token::Word(x, None) if x == "AND" || x == "OR" => {
+                error(format!("Expected a term before '{}'", x), position)
+            }
+            Token::Word(word, first_quote) => parse_term(word, *first_quote, position),
+        }
+    }
+}
+const FIELDS: &str = "name, desc, cat, tag, state, prio, status, completed, due, start, spent, \
+                      estimate and related";
+fn parse_term(
+    word: &str,
+    first_quote: Option<usize>,
+    position: usize,
+) -> Result<Query, QueryError> {
+    let operator = word
+        .find([':', '<', '>', '=', '!'])
+        .filter(|x| *x > 0 && first_quote.is_none_or(|quote| *x < quote));
+    let Some(operator_start) = operator else {
+        return Ok(Query::Text(word.to_lowercase()));
+    };
+    let field = word[..operator_start].to_lowercase();
+    let rest = &word[operator_start..];
+    let (comparison, negated, length) = [
+        ("<=", Comparison::LessOrEqual, false),
+        (">=", Comparison::GreaterOrEqual, false),
+        ("!=", Comparison::Equal, true),
+        ("<", Comparison::Less, false),
+        (">", Comparison::Greater, false),
+        ("=", Comparison::Equal, false),
+        (":", Comparison::Equal, false),
+    ]
+    .into_iter()
+    .find(|(operator, _, _)| rest.starts_with(operator))
+    .map(|(operator, comparison, negated)| (comparison, negated, operator.len()))
+    .ok_or(QueryError {
+        message: "Expected ':', '=', '!=', '<', '<=', '>' or '>='".into(),
+        position: position + operator_start,
+    })?;
+    let value = &word[operator_start + length..];
+    let value_position = position + operator_start + length;
+    if value.is_empty() {
+        return error(
+            format!(
+                "Expected a value after '{}'",
+                &word[..operator_start + length]
+            ),
+            value_position,
+        );
+    }
+    let ordered = comparison != Comparison::Equal;
+    let unordered = |query: Query| {
+        if ordered {
+            error(
+                format!("'{}' can't be compared with '<' or '>'", field),
+                position + operator_start,
+            )
+        } else {
+            Ok(query)
+        }
+    };
+    let optional =
+        |value: &str| (!value.eq_ignore_ascii_case("none")).then(|| value.to_lowercase());
+    let date = |value: &str| {
+        if value.eq_ignore_ascii_case("today") {
+            return Ok(Local::now().date_naive());
+        }
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").or(error(
+            "Expected a date like 2024-05-31 or 'today'",
+            value_position,
+        ))
+    };
+    let duration = |value: &str| {
+        parse_estimate(value).ok_or(QueryError {
+            message: "Expected a duration like 1h 30m or 45m".into(),
+            position: value_position,
+        })
+    };
+    fn test<T>(
+        comparison: Comparison,
+        value: &str,
+        parse: impl Fn(&str) -> Result<T, QueryError>,
+    ) -> Result<Test<T>, QueryError> {
+        Ok(match value.to_lowercase().as_str() {
+            "none" if comparison == Comparison::Equal => Test::None,
+            "any" if comparison == Comparison::Equal => Test::Any,
+            _ => Test::Compare(comparison, parse(value)?),
+        })
+    }
+    let query = match field.as_str() {
+        "name" => unordered(Query::Name(value.to_lowercase()))?,
+        "desc" | "description" => unordered(Query::Description(value.to_lowercase()))?,
+        "cat" | "category" => unordered(Query::Category(optional(value)))?,
+        "tag" => unordered(Query::Tag(value.to_lowercase()))?,
+        "state" => unordered(Query::State(optional(value)))?,
+        "prio" | "priority" => Query::Priority(test(comparison, value, |x| Ok(x.to_string()))?),
+        "status" => unordered(Query::Status(match value.to_lowercase().as_str() {
+            "ready" => Status::Ready,
+            "blocked" => Status::Blocked,
+            "completed" => Status::Completed,
+            _ => return error("Expected ready, blocked or completed", value_position),
+        }))?,
+        "completed" | "done" => Query::Completed(test(comparison, value, date)?),
+        "due" => Query::Due(test(comparison, value, date)?),
+        "start" | "starts" => Query::Starts(test(comparison, value, date)?),
+        "spent" => Query::Spent(test(comparison, value, duration)?),
+        "estimate" => Query::Estimate(test(comparison, value, duration)?),
+        "related" => unordered(Query::RelatedTo(
+            value
+                .parse()
+                .or(error("Expected the id of a task", value_position))?,
+        ))?,
+        _ => {
+            return error(
+                format!("There's no field '{}', the fields are {}", field, FIELDS),
+                position,
+            )
+        }
+    };
+    Ok(if negated {
+        Query::Not(Box::new(query))
+    } else {
+        query
+    })
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn matching(document: &KanbanDocument, query: &str) -> Vec<KanbanId> {
+        let query = Query::parse(query).unwrap();
+        document
+            .get_tasks()
+            .filter(|x| query.matches(x, document))
+            .map(|x| x.id)
+            .collect()
+    }
+    #[test]
+    fn test_query() {
+        let mut document = tests::make_document_easy(4, &[vec![1], vec![], vec![], vec![]]);
+        let mut task = document.get_task(0).unwrap().clone();
+        task.category = Some("Bug".into());
+        task.priority = Some("High".into());
+        task.tags = vec!["ui".into()];
+        document.replace_task(&task);
+        let mut task = document.get_task(1).unwrap().clone();
+        task.category = Some("bug".into());
+        task.priority = Some("Low".into());
+        task.tags = vec!["wontfix".into()];
+        task.name = "Crash on save".into();
+        document.replace_task(&task);
+        let mut task = document.get_task(2).unwrap().clone();
+        task.completed = Some(Utc::now());
+        task.estimate = Some(TimeDelta::hours(2));
+        document.replace_task(&task);
+
+        assert_eq!(
+            matching(
+                &document,
+                "category:bug AND NOT tag:wontfix AND priority>=Medium AND completed:none"
+            ),
+            vec![0]
+        );
+        assert_eq!(matching(&document, "cat:bug tag!=ui"), vec![1]);
+        assert_eq!(
+            matching(&document, "cat:none (completed:today OR status:ready)"),
+            vec![2, 3]
+        );
+        assert_eq!(matching(&document, "status:blocked"), vec![0]);
+        assert_eq!(matching(&document, "\"on save\""), vec![1]);
+        assert_eq!(matching(&document, "name:\"crash on\""), vec![1]);
+        assert_eq!(
+            matching(&document, "estimate>=\"1h 30m\" OR estimate>1.5"),
+            vec![2]
+        );
+        assert_eq!(matching(&document, "related:1"), vec![0, 1]);
+        assert_eq!(matching(&document, "prio:none"), vec![2, 3]);
+        assert_eq!(matching(&document, "").len(), 4);
+    }
+    #[test]
+    fn test_parse_errors() {
+        let position = |query: &str| Query::parse(query).unwrap_err().position;
+        assert_eq!(position("cat:bug AND"), 11);
+        assert_eq!(position("(cat:bug OR tag:ui"), 0);
+        assert_eq!(position("cat:bug)"), 7);
+        assert_eq!(position("tag:ui OR colour:red"), 10);
+        assert_eq!(position("due<tomorrow"), 4);
+        assert_eq!(position("name<x"), 4);
+        assert_eq!(position("tag:\"unclosed"), 4);
+        assert!(Query::parse("AND tag:ui").is_err());
+        let document = tests::make_document_easy(1, &[]);
+        assert!(Query::parse("prio>Urgent")
+            .unwrap()
+            .check(&document)
+            .is_err());
+    }
+}
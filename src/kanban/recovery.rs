@@ -0,0 +1,290 @@
+//! Keeping unsaved changes safe until the document is saved.
+//!
+//! Two files are kept alongside a document with unsaved changes:
+//!
+//! * `<name>.kan.recovery`, a snapshot of the whole document written every so often.
+//! * `<name>.kan.journal`, one line per change since the document was last saved, so the
+//!   changes made after the latest snapshot aren't lost either.
+//!
+//! Both are removed when the document is saved, so finding either of them when opening a
+//! document means something went wrong before the changes could be saved.
+use super::{
+    persistence::{self, DocumentError},
+    undo::Target,
+    views::SavedView,
+    workflow::WorkflowState,
+    KanbanCategoryStyle, KanbanDocument, KanbanId, KanbanItem,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+/// The state of part of the document after a change, as recorded in the journal.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum JournalEntry {
+    /// Boxed as tasks are much larger than the other entries
+    Upsert(Box<KanbanItem>),
+    Remove(KanbanId),
+    /// A priority's value, or `None` if it was removed
+    Priority(String, Option<i32>),
+    /// A category's style, or `None` if it was removed
+    Category(String, Option<KanbanCategoryStyle>),
+    Workflow(Vec<WorkflowState>),
+    Views(Vec<SavedView>),
+}
+impl JournalEntry {
+    /// The entries recording the current state of the parts of the document that were
+    /// touched, usually those from [`super::undo::UndoItem::touched`].
+    pub fn capture(document: &KanbanDocument, touched: &[Target]) -> Vec<Self> {
+        touched
+            .iter()
+            .map(|target| match target {
+                Target::Task(id) => match document.get_task(*id) {
+                    Some(task) => JournalEntry::Upsert(Box::new(task.clone())),
+                    None => JournalEntry::Remove(*id),
+                },
+                Target::Priority(name) => {
+                    JournalEntry::Priority(name.clone(), document.priorities.get(name).copied())
+                }
+                Target::Category(name) => {
+                    JournalEntry::Category(name.clone(), document.categories.get(name).copied())
+                }
+                Target::Workflow => JournalEntry::Workflow(document.workflow.clone()),
+                Target::Views => JournalEntry::Views(document.views.clone()),
+            })
+            .collect()
+    }
+    pub fn apply(&self, document: &mut KanbanDocument) {
+        match self {
+            JournalEntry::Upsert(task) => {
+                document.replace_task(task);
+            }
+            JournalEntry::Remove(id) => {
+                if let Some(task) = document.get_task(*id).cloned() {
+                    document.remove_task(&task);
+                }
+            }
+            JournalEntry::Priority(name, value) => {
+                match value {
+                    Some(value) => document.replace_priority(name, *value),
+                    None => document.remove_priority(name),
+                };
+            }
+            JournalEntry::Category(name, style) => {
+                match style {
+                    Some(style) => document.replace_category_style(name, *style),
+                    None => document.remove_category_style(name),
+                };
+            }
+            JournalEntry::Workflow(workflow) => {
+                document.replace_workflow(workflow.clone());
+            }
+            JournalEntry::Views(views) => {
+                document.replace_views(views.clone());
+            }
+        }
+    }
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+pub fn snapshot_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".recovery")
+}
+pub fn journal_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".journal")
+}
+/// Whether there are changes to the document at `path` that were never saved.
+pub fn has_unsaved_changes(path: &Path) -> bool {
+    snapshot_path(path).exists() || journal_path(path).exists()
+}
+/// Rebuild the document at `path` with the changes that weren't saved.
+///
+/// The latest snapshot, or the saved document if there isn't one yet, has the journal replayed
+/// over it. A line cut short by a crash ends the replay, and the result is repaired, as it can
+/// refer to tasks created after the snapshot.
+pub fn recover(path: &Path) -> Result<KanbanDocument, DocumentError> {
+    let snapshot = snapshot_path(path);
+    let base = if snapshot.exists() {
+        persistence::open(&snapshot)
+    } else {
+        persistence::open(path)
+    };
+    let mut document = match base {
+        Ok(document) => document,
+        Err(DocumentError::Integrity { document, .. }) => *document,
+        Err(x) => return Err(x),
+    };
+    match fs::File::open(journal_path(path)) {
+        Ok(file) => {
+            for line in io::BufReader::new(file).lines() {
+                match serde_json::from_str::<JournalEntry>(&line?) {
+                    Ok(entry) => entry.apply(&mut document),
+                    Err(_) => break,
+                }
+            }
+        }
+        Err(x) if x.kind() == io::ErrorKind::NotFound => (),
+        Err(x) => return Err(x.into()),
+    }
+    document.repair();
+    Ok(document)
+}
+/// Remove the recovery files for the document at `path`.
+pub fn discard(path: &Path) -> io::Result<()> {
+    for file in [snapshot_path(path), journal_path(path)] {
+        match fs::remove_file(file) {
+            Err(x) if x.kind() != io::ErrorKind::NotFound => return Err(x),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+enum Request {
+    Record(Vec<JournalEntry>),
+    Snapshot(Box<KanbanDocument>),
+    Discard,
+}
+/// Writes the recovery files for a single document from a background thread, so the interface
+/// never waits on the disk.
+///
+/// Requests are handled in the order they're made, so a snapshot requested before a save can't
+/// be written after the save removed the recovery files.
+pub struct Recovery {
+    tx: Option<mpsc::Sender<Request>>,
+    worker: Option<thread::JoinHandle<()>>,
+    /// Set once the worker has stopped, after which nothing more is recorded
+    stopped: Cell<bool>,
+}
+impl Recovery {
+    pub fn new(path: &Path) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_path_buf();
+        let worker = thread::spawn(move || {
+            let mut journal: Option<fs::File> = None;
+            for request in rx {
+                if let Err(x) = handle_request(&path, &mut journal, request) {
+                    eprintln!("Could not update the recovery files: {}", x);
+                }
+            }
+        });
+        Recovery {
+            tx: Some(tx),
+            worker: Some(worker),
+            stopped: Cell::new(false),
+        }
+    }
+    /// Add the state of the parts of the document touched by a change to the journal.
+    pub fn record(&self, entries: Vec<JournalEntry>) {
+        self.send(Request::Record(entries));
+    }
+    pub fn snapshot(&self, document: KanbanDocument) {
+        self.send(Request::Snapshot(Box::new(document)));
+    }
+    /// Remove the recovery files, as the document has been saved or its changes thrown away.
+    pub fn discard(&self) {
+        self.send(Request::Discard);
+    }
+    fn send(&self, request: Request) {
+        if self.stopped.get() {
+            return;
+        }
+        // The worker only stops once the sender is dropped, unless it panicked
+        if self.tx.as_ref().unwrap().send(request).is_err() {
+            eprintln!("Recovery stopped, unsaved changes won't be kept safe until saved");
+            self.stopped.set(true);
+        }
+    }
+}
+impl Drop for Recovery {
+    fn drop(&mut self) {
+        // Let the worker finish what it was asked to do
+        self.tx = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+fn handle_request(
+    path: &Path,
+    journal: &mut Option<fs::File>,
+    request: Request,
+) -> Result<(), DocumentError> {
+    match request {
+        Request::Record(entries) => {
+            if journal.is_none() {
+                *journal = Some(
+                    fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(journal_path(path))?,
+                );
+            }
+            let file = journal.as_mut().unwrap();
+            let mut text = String::new();
+            for entry in entries.iter() {
+                text.push_str(&serde_json::to_string(entry)?);
+                text.push('\n');
+            }
+            // Written in one go so a crash is less likely to leave half an entry behind
+            file.write_all(text.as_bytes())?;
+            file.sync_data()?;
+        }
+        Request::Snapshot(document) => persistence::save(&snapshot_path(path), &document)?,
+        Request::Discard => {
+            *journal = None;
+            discard(path)?;
+        }
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_recover() {
+        let path =
+            std::env::temp_dir().join(format!("kanbanrs-recovery-{}.kan", std::process::id()));
+        let mut document = KanbanDocument::new();
+        let mut parent = document.get_new_task();
+        parent.name = "Saved".into();
+        document.replace_task(&parent);
+        persistence::save(&path, &document).unwrap();
+        assert!(!has_unsaved_changes(&path));
+
+        let recovery = Recovery::new(&path);
+        let mut child = document.get_new_task();
+        child.name = "Unsaved".into();
+        let undo = document.replace_task(&child);
+        recovery.record(JournalEntry::capture(&document, &undo.touched()));
+        recovery.snapshot(document.clone());
+        // Only in the journal after the snapshot
+        parent.add_child(&child);
+        let undo = document.replace_task(&parent);
+        recovery.record(JournalEntry::capture(&document, &undo.touched()));
+        drop(recovery);
+
+        assert!(has_unsaved_changes(&path));
+        let recovered = recover(&path).unwrap();
+        assert_eq!(recovered.get_task(child.id).unwrap().name, "Unsaved");
+        assert!(recovered
+            .get_task(parent.id)
+            .unwrap()
+            .child_tasks
+            .contains(&child.id));
+
+        Recovery::new(&path).discard();
+        assert!(!has_unsaved_changes(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}
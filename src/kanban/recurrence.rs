@@ -0,0 +1,206 @@
+//! Tasks that come back after they're completed, such as chores.
+//!
+//! Completing a recurring task, whether with [`KanbanDocument::complete_task`] or by saving it
+//! completed with [`KanbanDocument::update_task`], creates its next occurrence, due on the next
+//! date the rule gives. The rule moves to the new task, so the completed one can be reopened and
+//! completed again without creating another.
+use super::*;
+use chrono::{Days, Months, NaiveDate, Weekday};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    /// On each of the days of the week given, every week if none are
+    Weekly(Vec<Weekday>),
+    EveryNDays(u32),
+    /// On this day of every month, or on the last day of months that are too short
+    Monthly(u32),
+}
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Daily => write!(f, "Every day"),
+            Self::Weekly(days) if days.is_empty() => write!(f, "Every week"),
+            Self::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|x| x.to_string()).collect();
+                write!(f, "Every {}", days.join(", "))
+            }
+            Self::EveryNDays(n) => write!(f, "Every {} days", n),
+            Self::Monthly(day) => write!(f, "On day {} of every month", day),
+        }
+    }
+}
+impl Recurrence {
+    /// The first occurrence after `after`, at the same time of day in the local time zone.
+    pub fn next_after(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        let local = after.with_timezone(&Local);
+        let date = local.date_naive();
+        let next = match self {
+            Self::Daily => date + Days::new(1),
+            Self::EveryNDays(n) => date + Days::new(u64::from(*n).max(1)),
+            Self::Weekly(days) => (1..=7)
+                .map(|x| date + Days::new(x))
+                .find(|x| days.contains(&x.weekday()))
+                .unwrap_or(date + Days::new(7)),
+            Self::Monthly(day) => {
+                let this_month = day_of_month(date.with_day(1).unwrap(), *day);
+                if this_month > date {
+                    this_month
+                } else {
+                    day_of_month(date.with_day(1).unwrap() + Months::new(1), *day)
+                }
+            }
+        };
+        next.and_time(local.time())
+            .and_local_timezone(Local)
+            .earliest()
+            // The time was skipped over by a change to daylight saving time
+            .map_or(after + (next - date), |x| x.to_utc())
+    }
+}
+/// The day in the month starting on `first`, or the month's last day if it's too short.
+fn day_of_month(first: NaiveDate, day: u32) -> NaiveDate {
+    let last = (first + Months::new(1) - Days::new(1)).day();
+    first.with_day(day.clamp(1, last)).unwrap()
+}
+impl KanbanDocument {
+    /// Mark the task completed, creating its next occurrence if it recurs, and return the item
+    /// that undoes all of it.
+    ///
+    /// The next occurrence is due on the first date the rule gives after the task was due, or
+    /// after now if it had no due date, skipping any dates that have already passed. It has the
+    /// same name, description, category, tags, priority and parents, but none of the children
+    /// or time logged.
+    pub fn complete_task(&mut self, id: KanbanId) -> Option<UndoItem> {
+        let mut task = self.get_task(id)?.clone();
+        if task.completed.is_some() {
+            return None;
+        }
+        task.completed = Some(Utc::now());
+        Some(self.update_task(&task))
+    }
+    /// Replace the task as [`KanbanDocument::replace_task`] does, creating its next occurrence
+    /// if this completes a recurring task, and return the item that undoes all of it.
+    pub fn update_task(&mut self, item: &KanbanItem) -> UndoItem {
+        let completing = item.completed.is_some()
+            && self
+                .get_task(item.id)
+                .is_some_and(|x| x.completed.is_none());
+        let mut task = item.clone();
+        let recurrence = match task.recurrence.take() {
+            Some(recurrence) if completing => recurrence,
+            _ => return self.replace_task(item),
+        };
+        let now = Utc::now();
+        self.begin_transaction();
+        self.replace_task(&task);
+        let mut due = recurrence.next_after(task.due.unwrap_or(now));
+        while due <= now {
+            due = recurrence.next_after(due);
+        }
+        let mut next = self.get_new_task();
+        next.name = task.name.clone();
+        next.description = task.description.clone();
+        next.category = task.category.clone();
+        next.tags = task.tags.clone();
+        next.priority = task.priority.clone();
+        next.start_after = task
+            .start_after
+            .zip(task.due)
+            .map(|(start, former_due)| start + (due - former_due));
+        next.due = Some(due);
+        next.recurrence = Some(recurrence);
+        self.replace_task(&next);
+        let parents: Vec<KanbanItem> = self.parents_of(task.id).into_iter().cloned().collect();
+        for mut parent in parents.into_iter() {
+            parent.add_child(&next);
+            self.replace_task(&parent);
+        }
+        self.commit_transaction().unwrap()
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+    fn local(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(year, month, day, 9, 0, 0)
+            .unwrap()
+            .to_utc()
+    }
+    #[test]
+    fn test_next_after() {
+        // A Friday
+        let start = local(2024, 5, 31);
+        assert_eq!(Recurrence::Daily.next_after(start), local(2024, 6, 1));
+        assert_eq!(
+            Recurrence::EveryNDays(3).next_after(start),
+            local(2024, 6, 3)
+        );
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]).next_after(start),
+            local(2024, 6, 3)
+        );
+        assert_eq!(
+            Recurrence::Weekly(Vec::new()).next_after(start),
+            local(2024, 6, 7)
+        );
+        assert_eq!(
+            Recurrence::Monthly(31).next_after(start),
+            local(2024, 6, 30)
+        );
+        assert_eq!(
+            Recurrence::Monthly(31).next_after(local(2024, 5, 30)),
+            local(2024, 5, 31)
+        );
+    }
+    #[test]
+    fn test_complete_task() {
+        let mut document = tests::make_document_easy(2, &[vec![1]]);
+        let mut chore = document.get_task(1).unwrap().clone();
+        chore.name = "Rotate secrets".into();
+        chore.tags.push("security".into());
+        chore.due = Some(Utc::now() - TimeDelta::days(1));
+        chore.recurrence = Some(Recurrence::Weekly(Vec::new()));
+        document.replace_task(&chore);
+
+        let undo = document.complete_task(1).unwrap();
+        let original = document.get_task(1).unwrap();
+        assert!(original.completed.is_some());
+        assert!(original.recurrence.is_none());
+        let next = document.get_task(2).unwrap();
+        assert_eq!(next.name, "Rotate secrets");
+        assert_eq!(next.tags, vec!["security".to_string()]);
+        assert_eq!(next.due, Some(chore.due.unwrap() + TimeDelta::days(7)));
+        assert!(next.completed.is_none());
+        assert!(document.get_task(0).unwrap().child_tasks.contains(&2));
+
+        undo.apply(&mut document);
+        assert!(document.get_task(2).is_none());
+        assert!(document.get_task(1).unwrap().completed.is_none());
+        assert!(!document.get_task(0).unwrap().child_tasks.contains(&2));
+        assert_eq!(
+            document.get_task(1).unwrap().recurrence,
+            Some(Recurrence::Weekly(Vec::new()))
+        );
+    }
+    #[test]
+    fn test_complete_by_moving() {
+        let mut document = tests::make_document_easy(1, &[]);
+        let mut chore = document.get_task(0).unwrap().clone();
+        chore.recurrence = Some(Recurrence::Daily);
+        document.replace_task(&chore);
+        // Dragging the task into the Completed column
+        assert!(chore.move_to_column(workflow::DEFAULT_COLUMNS.len() - 1, &document));
+        document.update_task(&chore);
+        assert!(document.get_task(0).unwrap().recurrence.is_none());
+        let next = document.get_task(1).unwrap();
+        assert!(next.completed.is_none());
+        assert_eq!(next.recurrence, Some(Recurrence::Daily));
+
+        // Saving it again once it's completed doesn't create another
+        document.update_task(&chore);
+        assert!(document.get_task(2).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+use super::time_report::{format_hours, Grouping, TimeReport};
+use super::*;
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use egui::ComboBox;
+
+pub struct ReportWindow {
+    pub open: bool,
+    from: String,
+    to: String,
+    grouping: Grouping,
+}
+impl Default for ReportWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ReportWindow {
+    pub fn new() -> Self {
+        let mut window = ReportWindow {
+            open: false,
+            from: String::new(),
+            to: String::new(),
+            grouping: Grouping::Day,
+        };
+        window.set_range(
+            Local::now().date_naive().week(Weekday::Mon).first_day(),
+            Local::now().date_naive(),
+        );
+        window
+    }
+    fn set_range(&mut self, from: NaiveDate, to: NaiveDate) {
+        self.from = from.format("%Y-%m-%d").to_string();
+        self.to = to.format("%Y-%m-%d").to_string();
+    }
+    /// Show the report
+    /// * `document` the document to report on
+    /// * `ui` The UI instance
+    ///
+    /// returns the report when it's to be exported.
+    pub fn show(&mut self, document: &KanbanDocument, ui: &mut egui::Ui) -> Option<TimeReport> {
+        let today = Local::now().date_naive();
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.text_edit_singleline(&mut self.from);
+            ui.label("To");
+            ui.text_edit_singleline(&mut self.to);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("This week").clicked() {
+                self.set_range(today.week(Weekday::Mon).first_day(), today);
+            }
+            if ui.button("This month").clicked() {
+                self.set_range(today.with_day(1).unwrap(), today);
+            }
+            if ui.button("Last month").clicked() {
+                let first = today.with_day(1).unwrap();
+                self.set_range(
+                    (first - Days::new(1)).with_day(1).unwrap(),
+                    first - Days::new(1),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Group by");
+            ComboBox::from_id_salt("Report grouping")
+                .selected_text(self.grouping.to_string())
+                .show_ui(ui, |ui| {
+                    for grouping in Grouping::ALL {
+                        ui.selectable_value(&mut self.grouping, grouping, grouping.to_string());
+                    }
+                });
+        });
+        let parse = |x: &str| NaiveDate::parse_from_str(x.trim(), "%Y-%m-%d").ok();
+        let (Some(from), Some(to)) = (parse(&self.from), parse(&self.to)) else {
+            ui.label(
+                RichText::new("Dates are written like 2024-05-31")
+                    .color(ui.visuals().error_fg_color),
+            );
+            return None;
+        };
+        let report = TimeReport::new(document, from, to, self.grouping);
+        ui.separator();
+        ScrollArea::vertical()
+            .id_salt("Report")
+            .max_height(ui.available_height() - 80.0)
+            .show(ui, |ui| {
+                egui::Grid::new("Report rows").striped(true).show(ui, |ui| {
+                    for (name, duration) in report.rows.iter() {
+                        ui.label(name);
+                        ui.label(format!("{}h", format_hours(duration)));
+                        ui.end_row();
+                    }
+                });
+            });
+        ui.separator();
+        ui.label(RichText::new(format!("Total: {}h", format_hours(&report.total))).strong());
+        if !report.undated.is_zero() {
+            ui.label(format!(
+                "{}h entered by hand isn't on any day and isn't included",
+                format_hours(&report.undated)
+            ));
+        }
+        ui.button("Export as CSV").clicked().then_some(report)
+    }
+}
@@ -0,0 +1,123 @@
+//! Due dates and start dates, and how urgent they make a task.
+//!
+//! A task with a start date isn't offered in the queue until that date arrives, and a task with
+//! a due date climbs the queue as the date approaches, ahead of tasks with a higher priority
+//! once it's close enough.
+use super::*;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+
+/// How many days before its due date a task starts gaining urgency.
+pub const URGENCY_HORIZON_DAYS: i64 = 14;
+
+/// The format dates are written in, and the one accepted by [`parse_date`] along with a bare
+/// date.
+pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Read a date in the local time zone, written as `2024-05-31 17:00` or `2024-05-31`.
+///
+/// A bare date is taken to mean `default_time` on that day, such as the end of the day for a due
+/// date.
+pub fn parse_date(text: &str, default_time: NaiveTime) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    let naive = NaiveDateTime::parse_from_str(text, DATE_FORMAT)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|x| x.and_time(default_time))
+        })?;
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|x| x.with_timezone(&Utc))
+}
+/// Write a date in the local time zone, in a form [`parse_date`] accepts.
+pub fn format_date(date: &DateTime<Utc>) -> String {
+    date.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+/// The time a bare due date refers to: the end of the day.
+pub fn end_of_day() -> NaiveTime {
+    NaiveTime::from_hms_opt(23, 59, 0).unwrap()
+}
+
+impl KanbanItem {
+    /// Whether the task's start date, if it has one, has arrived.
+    pub fn has_started(&self, now: DateTime<Utc>) -> bool {
+        self.start_after.is_none_or(|start| start <= now)
+    }
+    /// Whether the task's due date has passed without it being completed.
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        self.completed.is_none() && self.due.is_some_and(|due| due < now)
+    }
+    /// How much the due date adds to the task's priority value in the queue.
+    ///
+    /// Nothing until [`URGENCY_HORIZON_DAYS`] before it's due, then one for each day closer it
+    /// gets, and one more for each day it's overdue.
+    pub fn urgency(&self, now: DateTime<Utc>) -> i32 {
+        let Some(due) = self.due else {
+            return 0;
+        };
+        let remaining = due - now;
+        // Rounded down, so a task due later today counts as due in zero days
+        let days = remaining.num_days() - i64::from(remaining < TimeDelta::zero());
+        (URGENCY_HORIZON_DAYS - days).clamp(0, i32::MAX as i64) as i32
+    }
+}
+impl KanbanDocument {
+    /// The value the queue is ordered by, the task's priority value combined with its urgency.
+    pub fn queue_score(&self, id: &KanbanId, now: DateTime<Utc>) -> i32 {
+        self.task_priority_value(id)
+            .saturating_add(self.tasks[id].urgency(now))
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_urgency() {
+        let now = Utc::now();
+        let mut task = KanbanItem::default();
+        assert_eq!(task.urgency(now), 0);
+        task.due = Some(now + TimeDelta::days(30));
+        assert_eq!(task.urgency(now), 0);
+        task.due = Some(now + TimeDelta::hours(2));
+        assert_eq!(task.urgency(now), URGENCY_HORIZON_DAYS as i32);
+        assert!(!task.is_overdue(now));
+        task.due = Some(now - TimeDelta::hours(2));
+        assert_eq!(task.urgency(now), URGENCY_HORIZON_DAYS as i32 + 1);
+        assert!(task.is_overdue(now));
+        task.completed = Some(now);
+        assert!(!task.is_overdue(now));
+    }
+    #[test]
+    fn test_queue() {
+        let now = Utc::now();
+        let mut document = tests::make_document_easy(3, &[]);
+        document.replace_priority("High", 5);
+        let mut important = document.get_task(0).unwrap().clone();
+        important.priority = Some("High".into());
+        document.replace_task(&important);
+        let mut urgent = document.get_task(1).unwrap().clone();
+        urgent.due = Some(now + TimeDelta::days(1));
+        document.replace_task(&urgent);
+        let mut later = document.get_task(2).unwrap().clone();
+        later.start_after = Some(now + TimeDelta::days(1));
+        document.replace_task(&later);
+
+        let mut queue = queue_view::QueueState::new();
+        queue.update(
+            &document,
+            &filter::KanbanFilter::None,
+            &sorting::ItemSort::default(),
+        );
+        assert_eq!(queue.cached_ready, vec![1, 0]);
+    }
+    #[test]
+    fn test_parse_date() {
+        let date = parse_date("2024-05-31", end_of_day()).unwrap();
+        assert_eq!(format_date(&date), "2024-05-31 23:59");
+        let date = parse_date(" 2024-05-31 08:30 ", end_of_day()).unwrap();
+        assert_eq!(format_date(&date), "2024-05-31 08:30");
+        assert!(parse_date("tomorrow", end_of_day()).is_none());
+    }
+}
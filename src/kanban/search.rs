@@ -0,0 +1,314 @@
+//! Fuzzy searching over tasks.
+//!
+//! A search is made of terms separated by spaces, each of which has to match the task. A term
+//! matches anywhere in the task unless it starts with the field to look in: `name:`, `desc:`,
+//! `tag:`, `cat:` or `prio:`. Terms otherwise follow the syntax of `nucleo_matcher`, so `!wip`
+//! leaves out the tasks mentioning wip and `'parser` only matches the exact word.
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+#[cfg(feature = "gui")]
+use eframe::egui::{self, text::LayoutJob, Color32, TextFormat};
+use nucleo_matcher::{
+    pattern::{Atom, CaseMatching, Normalization},
+    Config, Matcher, Utf32Str,
+};
+
+#[cfg(feature = "gui")]
+use super::SummaryAction;
+use super::{filter::KanbanFilter, sorting::ItemSort, KanbanDocument, KanbanId, KanbanItem};
+
+/// A part of a task that can be searched on its own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Field {
+    Name,
+    Description,
+    Tags,
+    Category,
+    Priority,
+}
+impl Field {
+    pub const ALL: [Field; 5] = [
+        Self::Name,
+        Self::Description,
+        Self::Tags,
+        Self::Category,
+        Self::Priority,
+    ];
+    /// What a match in the field is multiplied by, so a task whose name matches comes before one
+    /// that only mentions the same thing somewhere in its description.
+    pub fn weight(&self) -> u32 {
+        match self {
+            Self::Name => 4,
+            Self::Tags | Self::Category => 3,
+            Self::Priority => 2,
+            Self::Description => 1,
+        }
+    }
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "name" => Some(Self::Name),
+            "desc" | "description" => Some(Self::Description),
+            "tag" | "tags" => Some(Self::Tags),
+            "cat" | "category" => Some(Self::Category),
+            "prio" | "priority" => Some(Self::Priority),
+            _ => None,
+        }
+    }
+    /// The text of the field, with the tags separated by spaces.
+    pub fn text<'a>(&self, task: &'a KanbanItem) -> Cow<'a, str> {
+        match self {
+            Self::Name => Cow::Borrowed(&task.name),
+            Self::Description => Cow::Borrowed(&task.description),
+            Self::Tags => Cow::Owned(task.tags.join(" ")),
+            Self::Category => Cow::Borrowed(task.category.as_deref().unwrap_or("")),
+            Self::Priority => Cow::Borrowed(task.priority.as_deref().unwrap_or("")),
+        }
+    }
+}
+/// A term of the search, and the field it's limited to if there is one.
+#[derive(Clone, Debug)]
+struct Term {
+    field: Option<Field>,
+    atom: Atom,
+}
+fn parse_terms(prompt: &str) -> Vec<Term> {
+    prompt
+        .split_whitespace()
+        .filter_map(|word| {
+            // Both `!tag:wip` and `tag:!wip` leave out what's tagged wip
+            let (negation, scoped) = match word.strip_prefix('!') {
+                Some(rest) => ("!", rest),
+                None => ("", word),
+            };
+            let field = scoped.split_once(':').and_then(|(prefix, text)| {
+                Some((Field::from_prefix(&prefix.to_lowercase())?, text))
+            });
+            // Anything else before a colon is part of what's being searched for
+            let (field, text) = match field {
+                Some((field, text)) => (Some(field), Cow::Owned(format!("{}{}", negation, text))),
+                None => (None, Cow::Borrowed(word)),
+            };
+            let atom = Atom::parse(&text, CaseMatching::Smart, Normalization::Smart);
+            (!atom.needle_text().is_empty()).then_some(Term { field, atom })
+        })
+        .collect()
+}
+/// Where a search matched a task, as the positions of the matching characters in each field.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Highlights(HashMap<Field, Vec<u32>>);
+impl Highlights {
+    /// The positions in the field's text that matched, in order.
+    pub fn in_field(&self, field: Field) -> &[u32] {
+        self.0.get(&field).map_or(&[], |x| x.as_slice())
+    }
+}
+/// Score the task against every term, none if any term doesn't match. Each term counts its best
+/// match, weighted by the field it's in, and everywhere it matched is added to `highlights`.
+fn score(
+    terms: &[Term],
+    task: &KanbanItem,
+    matcher: &mut Matcher,
+    highlights: &mut Highlights,
+) -> Option<u32> {
+    let mut buffer = Vec::new();
+    let mut total = 0;
+    for term in terms {
+        let fields = match &term.field {
+            Some(field) => std::slice::from_ref(field),
+            None => &Field::ALL[..],
+        };
+        let mut best: Option<u32> = None;
+        for field in fields {
+            let text = field.text(task);
+            let mut indices = Vec::new();
+            match term
+                .atom
+                .indices(Utf32Str::new(&text, &mut buffer), matcher, &mut indices)
+            {
+                Some(score) => {
+                    best = best.max(Some(u32::from(score) * field.weight()));
+                    highlights.0.entry(*field).or_default().extend(indices);
+                }
+                // What's left out can't be in any of the fields
+                None if term.atom.negative => return None,
+                None => (),
+            }
+        }
+        total += best?;
+    }
+    for indices in highlights.0.values_mut() {
+        indices.sort_unstable();
+        indices.dedup();
+    }
+    Some(total)
+}
+
+#[derive(Clone, Default)]
+pub struct SearchState {
+    pub matched_ids: Vec<i32>,
+    pub search_prompt: String,
+    /// Order the matches by the sort, with how well they match only breaking ties, rather
+    /// than the other way around
+    pub sort_first: bool,
+    /**
+    The former search prompt, if search_prompt and former_search_prompt are in disagreement
+    the matched_ids must be rebuilt.
+    */
+    former_search_prompt: String,
+    matcher: Matcher,
+    terms: Vec<Term>,
+    /// Where each of the matched tasks matched
+    highlights: HashMap<KanbanId, Highlights>,
+}
+
+impl SearchState {
+    pub fn new() -> Self {
+        SearchState {
+            matched_ids: Vec::new(),
+            search_prompt: String::new(),
+            sort_first: false,
+            former_search_prompt: String::new(),
+            matcher: Matcher::new(Config::DEFAULT),
+            terms: Vec::new(),
+            highlights: HashMap::new(),
+        }
+    }
+    pub fn force_update(&mut self) {
+        self.matched_ids.clear();
+    }
+    /// Where the task matched, if it did.
+    pub fn highlights(&self, id: KanbanId) -> Option<&Highlights> {
+        self.highlights.get(&id)
+    }
+    pub fn update(&mut self, document: &KanbanDocument, filter: &KanbanFilter, sort: &ItemSort) {
+        // This is *kinda* expensive, so we should avoid it if possible.
+        // The two conditions I can think of off the top of my head are that
+        // if the search prompt is unchanged, and the matched_ids are not empty, then
+        // we don't need to update.
+        if self.search_prompt == self.former_search_prompt && !self.matched_ids.is_empty() {
+            return;
+        }
+        if self.search_prompt != self.former_search_prompt {
+            self.terms = parse_terms(&self.search_prompt);
+            self.former_search_prompt = self.search_prompt.clone();
+        }
+        self.matched_ids.clear();
+        self.highlights.clear();
+        let mut values: Vec<(KanbanId, u32)> = Vec::new();
+        for i in document.get_tasks() {
+            if !filter.matches(i, document) {
+                continue;
+            }
+            let mut highlights = Highlights::default();
+            if let Some(score) = score(&self.terms, i, &mut self.matcher, &mut highlights) {
+                values.push((i.id, score));
+                self.highlights.insert(i.id, highlights);
+            }
+        }
+        values.sort_by(|(a, a_score), (b, b_score)| {
+            let by_score = b_score.cmp(a_score);
+            let by_sort = || {
+                sort.cmp_by(
+                    document.get_task(*a).unwrap(),
+                    document.get_task(*b).unwrap(),
+                    document,
+                )
+            };
+            if self.sort_first {
+                by_sort().then(by_score)
+            } else {
+                by_score.then_with(by_sort)
+            }
+        });
+        self.matched_ids.extend(values.drain(..).map(|x| x.0));
+    }
+}
+#[cfg(feature = "gui")]
+impl SearchState {
+    /// Show the matched tasks with the parts that matched highlighted.
+    pub fn show_results(
+        &self,
+        ui: &mut egui::Ui,
+        document: &KanbanDocument,
+        hovered_task: &mut Option<KanbanId>,
+        event_collector: &mut Vec<SummaryAction>,
+    ) -> f64 {
+        document.layout_rows(ui, &self.matched_ids, "SearchArea", |ui, item| {
+            event_collector.push(item.highlighted_summary(
+                document,
+                hovered_task,
+                ui,
+                self.highlights(item.id),
+            ));
+        })
+    }
+}
+/// The text with the characters at the positions in `indices` highlighted.
+///
+/// The matcher counts a character and the accents combined with it as one, so the highlights
+/// can land slightly off in text with combining accents.
+#[cfg(feature = "gui")]
+pub fn highlight(ui: &egui::Ui, text: &str, indices: &[u32], color: Color32) -> LayoutJob {
+    let normal = TextFormat::simple(egui::TextStyle::Body.resolve(ui.style()), color);
+    let matched = TextFormat {
+        color: ui.visuals().selection.stroke.color,
+        background: ui.visuals().selection.bg_fill,
+        ..normal.clone()
+    };
+    let mut job = LayoutJob::default();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (index, character) in text.chars().enumerate() {
+        let is_matched = indices.binary_search(&(index as u32)).is_ok();
+        if is_matched != run_matched && !run.is_empty() {
+            let format = if run_matched { &matched } else { &normal };
+            job.append(&std::mem::take(&mut run), 0.0, format.clone());
+        }
+        run_matched = is_matched;
+        run.push(character);
+    }
+    job.append(&run, 0.0, if run_matched { matched } else { normal });
+    job
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kanban::tests::make_document_easy;
+    fn search(document: &KanbanDocument, prompt: &str) -> SearchState {
+        let mut search = SearchState::new();
+        search.search_prompt = prompt.into();
+        search.update(document, &KanbanFilter::None, &ItemSort::default());
+        search
+    }
+    #[test]
+    fn test_fields() {
+        let mut document = make_document_easy(3, &[]);
+        for (id, name, description, tag) in [
+            (0, "Write docs", "Explain the parser", "docs"),
+            (1, "Parser", "", "bug"),
+            (2, "Release", "After the parser is done", "wip"),
+        ] {
+            let mut task = document.get_task(id).unwrap().clone();
+            task.name = name.into();
+            task.description = description.into();
+            task.tags.push(tag.into());
+            document.replace_task(&task);
+        }
+        // Matching the name counts for more than matching the description
+        assert_eq!(search(&document, "parser").matched_ids[0], 1);
+        assert_eq!(search(&document, "name:parser").matched_ids, vec![1]);
+        let mut scoped = search(&document, "desc:parser !tag:wip").matched_ids;
+        scoped.sort();
+        assert_eq!(scoped, vec![0]);
+        assert_eq!(search(&document, "!wip parser").matched_ids.len(), 2);
+        // An unknown field is searched for like any other text
+        assert!(search(&document, "nothing:parser").matched_ids.is_empty());
+
+        let result = search(&document, "name:parser");
+        let highlights = result.highlights(1).unwrap();
+        assert_eq!(highlights.in_field(Field::Name), &[0, 1, 2, 3, 4, 5]);
+        assert!(highlights.in_field(Field::Description).is_empty());
+        assert!(result.highlights(0).is_none());
+    }
+}
@@ -1,74 +1,211 @@
 use std::cmp::Ordering;
 
 use super::{KanbanDocument, KanbanId, KanbanItem};
+#[cfg(feature = "gui")]
 use eframe::egui::{self, ComboBox};
-#[derive(PartialEq, Copy, Clone)]
-pub enum ItemSort {
-    None,
+use serde::{Deserialize, Serialize};
+/// Something tasks can be put in order by.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum SortKey {
     Id,
-    Newest,
     Name,
     Category,
     Completed,
+    /// The value of the task's priority
+    Priority,
+    TimeSpent,
+    /// The number of child tasks
+    Children,
+    /// The number of tags
+    Tags,
+    Due,
 }
-impl From<ItemSort> for String {
-    fn from(value: ItemSort) -> Self {
+impl From<SortKey> for String {
+    fn from(value: SortKey) -> Self {
         match value {
-            ItemSort::None => "None",
-            ItemSort::Id => "Creation Order",
-            ItemSort::Newest => "Newest first",
-            ItemSort::Name => "Name",
-            ItemSort::Category => "Category",
-            ItemSort::Completed => "Completed",
+            SortKey::Id => "Creation order",
+            SortKey::Name => "Name",
+            SortKey::Category => "Category",
+            SortKey::Completed => "Completed",
+            SortKey::Priority => "Priority",
+            SortKey::TimeSpent => "Time spent",
+            SortKey::Children => "Child tasks",
+            SortKey::Tags => "Tags",
+            SortKey::Due => "Due date",
         }
         .to_owned()
     }
 }
-impl ItemSort {
-    pub fn cmp_by(&self, a: &KanbanItem, b: &KanbanItem) -> std::cmp::Ordering {
+impl SortKey {
+    pub const ALL: [SortKey; 9] = [
+        Self::Id,
+        Self::Name,
+        Self::Category,
+        Self::Completed,
+        Self::Priority,
+        Self::TimeSpent,
+        Self::Children,
+        Self::Tags,
+        Self::Due,
+    ];
+    /// The direction the key is sorted in when it's first picked, counts start with the most.
+    pub fn default_direction(&self) -> Direction {
+        match self {
+            Self::Priority | Self::TimeSpent | Self::Children | Self::Tags => Direction::Descending,
+            _ => Direction::Ascending,
+        }
+    }
+    fn compare(&self, a: &KanbanItem, b: &KanbanItem, document: &KanbanDocument) -> Ordering {
+        let priority = |x: &KanbanItem| {
+            x.priority
+                .as_ref()
+                .and_then(|x| document.priorities.get(x))
+                .copied()
+                .unwrap_or(0)
+        };
         match self {
-            Self::None => Ordering::Equal,
             Self::Id => a.id.cmp(&b.id),
-            Self::Newest => b.id.cmp(&a.id),
             Self::Name => a.name.cmp(&b.name),
             Self::Category => a.category.cmp(&b.category),
             Self::Completed => a.completed.cmp(&b.completed),
+            Self::Priority => priority(a).cmp(&priority(b)),
+            Self::TimeSpent => a.time_records.duration().cmp(&b.time_records.duration()),
+            Self::Children => a.child_tasks.len().cmp(&b.child_tasks.len()),
+            Self::Tags => a.tags.len().cmp(&b.tags.len()),
+            Self::Due => a.due.cmp(&b.due),
         }
     }
-    pub fn sort_by(&self, ids: &mut [KanbanId], document: &KanbanDocument) {
+}
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Ascending,
+    Descending,
+}
+impl Direction {
+    pub fn reversed(&self) -> Self {
         match self {
-            Self::None => (),
-            Self::Id => ids.sort_by_key(|x| document.get_task(*x).as_ref().unwrap().id),
-            Self::Newest => {
-                ids.sort_by_key(|x| document.get_task(*x).unwrap().id);
-                ids.reverse()
-            }
-            Self::Name => ids.sort_by_key(|x| &document.get_task(*x).as_ref().unwrap().name),
-            Self::Category => {
-                ids.sort_by_key(|x| &document.get_task(*x).as_ref().unwrap().category)
-            }
-            Self::Completed => {
-                ids.sort_by_key(|x| &document.get_task(*x).as_ref().unwrap().completed)
-            }
+            Self::Ascending => Self::Descending,
+            Self::Descending => Self::Ascending,
         }
     }
+    fn arrow(&self) -> &'static str {
+        match self {
+            Self::Ascending => "⏶",
+            Self::Descending => "⏷",
+        }
+    }
+}
+/// The keys tasks are sorted by, each only deciding between tasks the keys before it found equal.
+/// Sorting by no keys leaves tasks in the order they're in.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ItemSort {
+    pub keys: Vec<(SortKey, Direction)>,
+}
+impl From<&ItemSort> for String {
+    fn from(value: &ItemSort) -> Self {
+        if value.keys.is_empty() {
+            return "None".to_owned();
+        }
+        value
+            .keys
+            .iter()
+            .map(|(key, direction)| format!("{} {}", String::from(*key), direction.arrow()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+impl ItemSort {
+    pub fn by(key: SortKey, direction: Direction) -> Self {
+        ItemSort {
+            keys: vec![(key, direction)],
+        }
+    }
+    /// Break ties with another key.
+    pub fn then(mut self, key: SortKey, direction: Direction) -> Self {
+        self.keys.push((key, direction));
+        self
+    }
+    pub fn cmp_by(&self, a: &KanbanItem, b: &KanbanItem, document: &KanbanDocument) -> Ordering {
+        self.keys
+            .iter()
+            .map(|(key, direction)| match (key, direction) {
+                // Tasks without a due date go last either way
+                (SortKey::Due, _) if a.due.is_none() != b.due.is_none() => {
+                    a.due.is_none().cmp(&b.due.is_none())
+                }
+                (_, Direction::Ascending) => key.compare(a, b, document),
+                (_, Direction::Descending) => key.compare(b, a, document),
+            })
+            .find(|x| x.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+    pub fn sort_by(&self, ids: &mut [KanbanId], document: &KanbanDocument) {
+        if self.keys.is_empty() {
+            return;
+        }
+        ids.sort_by(|a, b| {
+            self.cmp_by(
+                document.get_task(*a).unwrap(),
+                document.get_task(*b).unwrap(),
+                document,
+            )
+        });
+    }
+}
+#[cfg(feature = "gui")]
+impl ItemSort {
+    /// A row with a combobox for each key, a button to flip its direction and one to remove it,
+    /// followed by a button adding another key.
     pub fn combobox(&mut self, ui: &mut egui::Ui) -> bool {
         let mut needs_sorting = false;
+        let mut removed = None;
         ui.label("Sort by");
-        ComboBox::from_id_salt("SortingScheme")
-            .selected_text(String::from(*self))
-            .show_ui(ui, |ui| {
-                needs_sorting = [
-                    ui.selectable_value(self, Self::None, "None"),
-                    ui.selectable_value(self, Self::Id, "Creation order"),
-                    ui.selectable_value(self, Self::Newest, "Newest First"),
-                    ui.selectable_value(self, Self::Name, "Name"),
-                    ui.selectable_value(self, Self::Category, "Category"),
-                    ui.selectable_value(self, Self::Completed, "Completed"),
-                ]
-                .iter()
-                .any(|x| x.clicked());
-            });
+        for (index, (key, direction)) in self.keys.iter_mut().enumerate() {
+            if index > 0 {
+                ui.label("then");
+            }
+            ComboBox::from_id_salt(("SortingScheme", index))
+                .selected_text(String::from(*key))
+                .show_ui(ui, |ui| {
+                    for option in SortKey::ALL {
+                        needs_sorting |= ui
+                            .selectable_value(key, option, String::from(option))
+                            .clicked();
+                    }
+                });
+            let hover = match direction {
+                Direction::Ascending => "Ascending",
+                Direction::Descending => "Descending",
+            };
+            if ui
+                .small_button(direction.arrow())
+                .on_hover_text(hover)
+                .clicked()
+            {
+                *direction = direction.reversed();
+                needs_sorting = true;
+            }
+            if ui.small_button("🗑").on_hover_text("Remove").clicked() {
+                removed = Some(index);
+            }
+        }
+        if let Some(index) = removed {
+            self.keys.remove(index);
+            needs_sorting = true;
+        }
+        let unused = SortKey::ALL
+            .into_iter()
+            .find(|x| self.keys.iter().all(|(key, _)| key != x));
+        if let Some(key) = unused {
+            if ui
+                .small_button("➕")
+                .on_hover_text("Sort by another key")
+                .clicked()
+            {
+                self.keys.push((key, key.default_direction()));
+                needs_sorting = true;
+            }
+        }
         needs_sorting
     }
 }
@@ -121,4 +258,29 @@ mod test {
         assert_eq!(task_comparison_completed_last(&b, &a), Ordering::Less);
         assert_eq!(a.id, thing[1]);
     }
+    #[test]
+    fn test_sort_keys() {
+        let mut document = KanbanDocument::new();
+        document.replace_priority("High", 10);
+        let mut tasks: Vec<KanbanItem> = (0..4).map(|_| document.get_new_task()).collect();
+        tasks[1].priority = Some("High".into());
+        tasks[3].priority = Some("High".into());
+        tasks[0].name = "b".into();
+        tasks[2].name = "a".into();
+        tasks[3].due = Some(Utc::now());
+        tasks.iter().for_each(|x| {
+            document.replace_task(x);
+        });
+        let mut ids = [0, 1, 2, 3];
+        let sort = ItemSort::by(SortKey::Priority, Direction::Descending)
+            .then(SortKey::Name, Direction::Ascending);
+        sort.sort_by(&mut ids, &document);
+        assert_eq!(ids, [1, 3, 2, 0]);
+        // Tasks without a due date stay last whichever way they're sorted
+        ItemSort::by(SortKey::Due, Direction::Descending).sort_by(&mut ids, &document);
+        assert_eq!(ids, [3, 1, 2, 0]);
+        ItemSort::default().sort_by(&mut ids, &document);
+        assert_eq!(ids, [3, 1, 2, 0]);
+        assert_eq!(String::from(&sort), "Priority ⏷, Name ⏶");
+    }
 }
@@ -0,0 +1,203 @@
+//! Counts of tasks over time, for burndown and cumulative flow charts.
+//!
+//! Documents don't record when tasks were created, only the order they were created in, so a
+//! task is taken to exist from the earliest sign of it or of any task created after it: a
+//! completion or some time logged. Tasks with no such sign are counted from the start. Tasks
+//! that were completed and then reopened count as never having been completed.
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+/// The number of tasks in each state at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowPoint {
+    pub time: DateTime<Utc>,
+    pub ready: usize,
+    pub blocked: usize,
+    pub completed: usize,
+}
+/// The tasks left in a tree at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BurndownPoint {
+    pub time: DateTime<Utc>,
+    pub remaining: usize,
+    /// Every task in the tree at the time, completed or not
+    pub total: usize,
+}
+/// What can be told about the document at earlier times.
+pub struct History<'a> {
+    document: &'a KanbanDocument,
+    /// The latest each task can have been created, tasks without any are counted from the start
+    created: HashMap<KanbanId, DateTime<Utc>>,
+}
+impl<'a> History<'a> {
+    pub fn new(document: &'a KanbanDocument) -> Self {
+        let mut created = HashMap::new();
+        let mut earliest: Option<DateTime<Utc>> = None;
+        // Newest first, so each task takes the earliest sign of the tasks after it
+        for task in document.tasks.values().rev() {
+            let first_logged = task
+                .time_records
+                .entries
+                .iter()
+                .filter_map(|(entry, _)| match entry {
+                    time_tracking::TimeEntry::Started(start)
+                    | time_tracking::TimeEntry::Concluded(start, _) => Some(*start),
+                    time_tracking::TimeEntry::InstanteousDuration(_) => None,
+                })
+                .min();
+            earliest = [earliest, task.completed, first_logged]
+                .into_iter()
+                .flatten()
+                .min();
+            if let Some(time) = earliest {
+                created.insert(task.id, time);
+            }
+        }
+        History { document, created }
+    }
+    /// The latest the task can have been created, if there's any sign of when.
+    pub fn created(&self, id: KanbanId) -> Option<DateTime<Utc>> {
+        self.created.get(&id).copied()
+    }
+    fn existed_at(&self, id: KanbanId, time: DateTime<Utc>) -> bool {
+        self.created(id).is_none_or(|x| x <= time)
+    }
+    fn completed_at(&self, id: KanbanId, time: DateTime<Utc>) -> bool {
+        self.document.tasks[&id]
+            .completed
+            .is_some_and(|x| x <= time)
+    }
+    /// The task's status at `time`, none if it didn't exist yet.
+    pub fn status_at(&self, id: KanbanId, time: DateTime<Utc>) -> Option<Status> {
+        if !self.existed_at(id, time) {
+            return None;
+        }
+        if self.completed_at(id, time) {
+            return Some(Status::Completed);
+        }
+        let blocked = self.document.tasks[&id]
+            .child_tasks
+            .iter()
+            .any(|x| self.existed_at(*x, time) && !self.completed_at(*x, time));
+        Some(if blocked {
+            Status::Blocked
+        } else {
+            Status::Ready
+        })
+    }
+    /// How many tasks were in each state at each of the times.
+    pub fn cumulative_flow(&self, times: &[DateTime<Utc>]) -> Vec<FlowPoint> {
+        times
+            .iter()
+            .map(|time| {
+                let mut point = FlowPoint {
+                    time: *time,
+                    ready: 0,
+                    blocked: 0,
+                    completed: 0,
+                };
+                for id in self.document.tasks.keys() {
+                    match self.status_at(*id, *time) {
+                        Some(Status::Ready) => point.ready += 1,
+                        Some(Status::Blocked) => point.blocked += 1,
+                        Some(Status::Completed) => point.completed += 1,
+                        None => (),
+                    }
+                }
+                point
+            })
+            .collect()
+    }
+    /// How many tasks were left at each of the times, in the tree under `root` or in the whole
+    /// document if none is given.
+    pub fn burndown(&self, root: Option<KanbanId>, times: &[DateTime<Utc>]) -> Vec<BurndownPoint> {
+        let tasks: HashSet<KanbanId> = match root {
+            Some(root) => {
+                let mut tasks = HashSet::new();
+                self.document.on_tree(root, 0, |_, id, _| {
+                    tasks.insert(id);
+                });
+                tasks
+            }
+            None => self.document.tasks.keys().copied().collect(),
+        };
+        times
+            .iter()
+            .map(|time| {
+                let existing = tasks.iter().filter(|x| self.existed_at(**x, *time));
+                let (remaining, total) = existing.fold((0, 0), |(remaining, total), id| {
+                    let done = self.completed_at(*id, *time);
+                    (remaining + usize::from(!done), total + 1)
+                });
+                BurndownPoint {
+                    time: *time,
+                    remaining,
+                    total,
+                }
+            })
+            .collect()
+    }
+}
+/// `samples` times spread evenly from `from` to `to`, including both.
+pub fn sample_times(from: DateTime<Utc>, to: DateTime<Utc>, samples: usize) -> Vec<DateTime<Utc>> {
+    let samples = samples.max(2);
+    let step = (to - from) / (samples as i32 - 1);
+    (0..samples).map(|x| from + step * x as i32).collect()
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+    #[test]
+    fn test_history() {
+        // 0 waits on 1 and 2, 3 was created last
+        let mut document = tests::make_document_easy(4, &[vec![1, 2], vec![], vec![], vec![]]);
+        let start = Utc::now() - TimeDelta::days(10);
+        let day = |x: i64| start + TimeDelta::days(x);
+        let mut complete = |id: KanbanId, time: DateTime<Utc>| {
+            let mut task = document.get_task(id).unwrap().clone();
+            task.completed = Some(time);
+            document.replace_task(&task);
+        };
+        complete(1, day(2));
+        complete(2, day(4));
+        complete(3, day(6));
+        let mut task = document.get_task(2).unwrap().clone();
+        task.time_records.entries.push((
+            time_tracking::TimeEntry::Concluded(day(1), day(1) + TimeDelta::hours(1)),
+            None,
+        ));
+        document.replace_task(&task);
+
+        let history = History::new(&document);
+        assert_eq!(history.created(0), Some(day(1)));
+        assert_eq!(history.created(3), Some(day(6)));
+        assert_eq!(history.status_at(3, day(5)), None);
+        assert_eq!(history.status_at(0, day(3)), Some(Status::Blocked));
+        assert_eq!(history.status_at(0, day(5)), Some(Status::Ready));
+
+        let times = sample_times(day(0), day(8), 5);
+        assert_eq!(times.len(), 5);
+        assert_eq!(times[4], day(8));
+        let flow = history.cumulative_flow(&times);
+        // Nothing is known about task 0 before day 1
+        assert_eq!(
+            (flow[0].ready, flow[0].blocked, flow[0].completed),
+            (0, 0, 0)
+        );
+        assert_eq!(
+            (flow[2].ready, flow[2].blocked, flow[2].completed),
+            (1, 0, 2)
+        );
+        assert_eq!(
+            (flow[4].ready, flow[4].blocked, flow[4].completed),
+            (1, 0, 3)
+        );
+
+        let burndown = history.burndown(Some(0), &times);
+        assert_eq!(burndown[1].remaining, 2);
+        assert_eq!(burndown[1].total, 3);
+        assert_eq!(burndown[4].remaining, 1);
+        assert_eq!(history.burndown(None, &times)[4].total, 4);
+    }
+}
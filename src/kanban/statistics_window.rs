@@ -0,0 +1,204 @@
+use super::statistics::{sample_times, History};
+use super::*;
+use chrono::TimeDelta;
+use egui::{ComboBox, DragValue, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+
+/// How many points each chart is drawn through
+const SAMPLES: usize = 60;
+const CHART_HEIGHT: f32 = 200.0;
+
+pub struct StatisticsWindow {
+    pub open: bool,
+    /// The task the burndown is for, the whole document if none
+    root: Option<KanbanId>,
+    /// How far back the charts go
+    days: u32,
+}
+impl Default for StatisticsWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl StatisticsWindow {
+    pub fn new() -> Self {
+        StatisticsWindow {
+            open: false,
+            root: None,
+            days: 30,
+        }
+    }
+    pub fn show(&mut self, document: &KanbanDocument, ui: &mut egui::Ui) {
+        if self.root.is_some_and(|x| document.get_task(x).is_none()) {
+            self.root = None;
+        }
+        let name_of = |id: Option<KanbanId>| {
+            id.and_then(|x| document.get_task(x))
+                .map_or("Every task".to_string(), |x| x.name.clone())
+        };
+        ui.horizontal(|ui| {
+            ui.label("Over the last");
+            ui.add(DragValue::new(&mut self.days).range(1..=3650));
+            ui.label("days");
+        });
+        let now = Utc::now();
+        let times = sample_times(now - TimeDelta::days(self.days.into()), now, SAMPLES);
+        let history = History::new(document);
+        ScrollArea::vertical().show(ui, |ui| {
+            ui.heading("Burndown");
+            ComboBox::from_label("Tasks under")
+                .selected_text(name_of(self.root))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.root, None, name_of(None));
+                    for task in document.get_tasks().filter(|x| !x.child_tasks.is_empty()) {
+                        ui.selectable_value(&mut self.root, Some(task.id), &task.name);
+                    }
+                });
+            let burndown = history.burndown(self.root, &times);
+            let scale = burndown.iter().map(|x| x.total).max().unwrap_or(0);
+            let total: Vec<f32> = burndown.iter().map(|x| x.total as f32).collect();
+            let remaining: Vec<f32> = burndown.iter().map(|x| x.remaining as f32).collect();
+            let rect = chart_area(ui, scale);
+            let painter = ui.painter_at(rect);
+            let weak = ui.visuals().weak_text_color();
+            painter.add(Shape::line(
+                points(rect, &total, scale),
+                Stroke::new(1.0, weak),
+            ));
+            painter.add(Shape::line(
+                points(rect, &remaining, scale),
+                Stroke::new(2.0, Color32::from_rgb(255, 140, 0)),
+            ));
+            // Where the tasks left would have to be done by to finish on time
+            let due = self.root.and_then(|x| document.get_task(x)?.due);
+            if let (Some(due), Some(first)) = (due, remaining.first()) {
+                let x = (due - times[0]).num_seconds() as f32
+                    / (now - times[0]).num_seconds().max(1) as f32;
+                let start = points(rect, &[*first], scale)[0];
+                let end = Pos2::new(rect.left() + x * rect.width(), rect.bottom());
+                painter.add(Shape::dashed_line(
+                    &[start, end],
+                    Stroke::new(1.0, ui.visuals().text_color()),
+                    6.0,
+                    4.0,
+                ));
+            }
+            time_axis(ui, &times);
+            legend(
+                ui,
+                &[
+                    ("Left to do", Color32::from_rgb(255, 140, 0)),
+                    ("All tasks", weak),
+                ],
+            );
+
+            ui.separator();
+            ui.heading("Cumulative flow");
+            let flow = history.cumulative_flow(&times);
+            let scale = flow
+                .iter()
+                .map(|x| x.completed + x.blocked + x.ready)
+                .max()
+                .unwrap_or(0);
+            let layers = [
+                (
+                    "Completed",
+                    Color32::from_rgb(80, 160, 80),
+                    flow.iter().map(|x| x.completed).collect::<Vec<_>>(),
+                ),
+                (
+                    "Blocked",
+                    Color32::from_rgb(200, 60, 60),
+                    flow.iter().map(|x| x.blocked).collect(),
+                ),
+                (
+                    "Ready",
+                    Color32::from_rgb(70, 130, 200),
+                    flow.iter().map(|x| x.ready).collect(),
+                ),
+            ];
+            let rect = chart_area(ui, scale);
+            let painter = ui.painter_at(rect);
+            let mut below = vec![0.0; flow.len()];
+            for (_, color, counts) in layers.iter() {
+                let above: Vec<f32> = below
+                    .iter()
+                    .zip(counts.iter())
+                    .map(|(below, count)| below + *count as f32)
+                    .collect();
+                let top = points(rect, &above, scale);
+                let bottom = points(rect, &below, scale);
+                // Each slice between two samples is convex, the whole band usually isn't
+                for index in 1..flow.len() {
+                    painter.add(Shape::convex_polygon(
+                        vec![bottom[index - 1], top[index - 1], top[index], bottom[index]],
+                        *color,
+                        Stroke::NONE,
+                    ));
+                }
+                below = above;
+            }
+            time_axis(ui, &times);
+            legend(
+                ui,
+                &layers
+                    .iter()
+                    .rev()
+                    .map(|(name, color, _)| (*name, *color))
+                    .collect::<Vec<_>>(),
+            );
+        });
+    }
+}
+/// Allocate the space for a chart, with the highest count labelled, and return where to draw.
+fn chart_area(ui: &mut egui::Ui, scale: usize) -> Rect {
+    let (response, painter) = ui.allocate_painter(
+        Vec2::new(ui.available_width(), CHART_HEIGHT),
+        Sense::hover(),
+    );
+    let rect = response.rect.shrink2(Vec2::new(30.0, 8.0));
+    let stroke = ui.visuals().noninteractive().bg_stroke;
+    painter.line_segment([rect.left_top(), rect.left_bottom()], stroke);
+    painter.line_segment([rect.left_bottom(), rect.right_bottom()], stroke);
+    for (count, y) in [(scale, rect.top()), (0, rect.bottom())] {
+        painter.text(
+            Pos2::new(rect.left() - 4.0, y),
+            egui::Align2::RIGHT_CENTER,
+            count.to_string(),
+            egui::FontId::monospace(10.0),
+            ui.visuals().text_color(),
+        );
+    }
+    rect
+}
+/// The values spread evenly across the chart, scaled so `scale` is at the top.
+fn points(rect: Rect, values: &[f32], scale: usize) -> Vec<Pos2> {
+    let step = rect.width() / (values.len().max(2) - 1) as f32;
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            Pos2::new(
+                rect.left() + step * index as f32,
+                rect.bottom() - rect.height() * value / scale.max(1) as f32,
+            )
+        })
+        .collect()
+}
+/// The first and last days under a chart.
+fn time_axis(ui: &mut egui::Ui, times: &[DateTime<Utc>]) {
+    let day = |x: &DateTime<Utc>| x.with_timezone(&Local).format("%Y-%m-%d").to_string();
+    ui.horizontal(|ui| {
+        ui.label(day(&times[0]));
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.label(day(&times[times.len() - 1]));
+        });
+    });
+}
+fn legend(ui: &mut egui::Ui, entries: &[(&str, Color32)]) {
+    ui.horizontal(|ui| {
+        for (name, color) in entries {
+            ui.label(RichText::new("■").color(*color));
+            ui.label(*name);
+        }
+    });
+}
@@ -0,0 +1,235 @@
+//! Totals of the time logged over a range of days, for timesheets.
+//!
+//! Time is counted on the day it was spent, so an entry running past midnight is split between
+//! both days and only the part inside the range counts. Durations entered by hand weren't spent
+//! on any particular day, so they're totalled separately instead.
+use super::*;
+use chrono::{Datelike, NaiveDate, TimeDelta};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Grouping {
+    Day,
+    Week,
+    Category,
+    /// Tasks with several tags count towards each of them
+    Tag,
+    /// Each task that isn't a child of another, along with everything under it
+    Tree,
+}
+impl Grouping {
+    pub const ALL: [Grouping; 5] = [
+        Grouping::Day,
+        Grouping::Week,
+        Grouping::Category,
+        Grouping::Tag,
+        Grouping::Tree,
+    ];
+}
+impl std::fmt::Display for Grouping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Grouping::Day => "Day",
+            Grouping::Week => "Week",
+            Grouping::Category => "Category",
+            Grouping::Tag => "Tag",
+            Grouping::Tree => "Task tree",
+        })
+    }
+}
+#[derive(Clone, Debug)]
+pub struct TimeReport {
+    pub grouping: Grouping,
+    /// The first and last days counted
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Each group and the time logged in it. Days and weeks are in order, the rest have the
+    /// most time first.
+    pub rows: Vec<(String, TimeDelta)>,
+    /// The time logged on every task, counting each task once however many groups it's in
+    pub total: TimeDelta,
+    /// The time entered by hand on the tasks, which isn't on any day
+    pub undated: TimeDelta,
+}
+impl TimeReport {
+    pub fn new(
+        document: &KanbanDocument,
+        from: NaiveDate,
+        to: NaiveDate,
+        grouping: Grouping,
+    ) -> Self {
+        let mut by_task: HashMap<KanbanId, TimeDelta> = HashMap::new();
+        let mut by_day: BTreeMap<NaiveDate, TimeDelta> = BTreeMap::new();
+        let mut undated = TimeDelta::zero();
+        for task in document.get_tasks() {
+            for (entry, _) in task.time_records.entries.iter() {
+                if let time_tracking::TimeEntry::InstanteousDuration(duration) = entry {
+                    undated += *duration;
+                }
+                for (day, duration) in entry.per_day() {
+                    if (from..=to).contains(&day) {
+                        *by_task.entry(task.id).or_default() += duration;
+                        *by_day.entry(day).or_default() += duration;
+                    }
+                }
+            }
+        }
+        let total = by_task.values().sum();
+        let mut rows: Vec<(String, TimeDelta)> = match grouping {
+            Grouping::Day => by_day
+                .into_iter()
+                .map(|(day, duration)| (day.format("%Y-%m-%d").to_string(), duration))
+                .collect(),
+            Grouping::Week => {
+                let mut weeks: BTreeMap<(i32, u32), TimeDelta> = BTreeMap::new();
+                for (day, duration) in by_day {
+                    let week = day.iso_week();
+                    *weeks.entry((week.year(), week.week())).or_default() += duration;
+                }
+                weeks
+                    .into_iter()
+                    .map(|((year, week), duration)| (format!("{}-W{:02}", year, week), duration))
+                    .collect()
+            }
+            Grouping::Category => group_by(&by_task, |id| {
+                vec![document.tasks[&id]
+                    .category
+                    .clone()
+                    .unwrap_or_else(|| "No category".into())]
+            }),
+            Grouping::Tag => group_by(&by_task, |id| {
+                let tags = &document.tasks[&id].tags;
+                if tags.is_empty() {
+                    vec!["No tags".into()]
+                } else {
+                    tags.clone()
+                }
+            }),
+            Grouping::Tree => {
+                let children: HashSet<KanbanId> = document
+                    .get_tasks()
+                    .flat_map(|x| x.child_tasks.iter().copied())
+                    .collect();
+                document
+                    .get_tasks()
+                    .filter(|x| !children.contains(&x.id))
+                    .map(|root| {
+                        // Shared subtasks can be reached along more than one path
+                        let mut seen: HashSet<KanbanId> = HashSet::new();
+                        let mut duration = TimeDelta::zero();
+                        document.on_tree(root.id, 0, |_, id, _| {
+                            if seen.insert(id) {
+                                duration += by_task.get(&id).copied().unwrap_or_default();
+                            }
+                        });
+                        (root.name.clone(), duration)
+                    })
+                    .filter(|(_, duration)| !duration.is_zero())
+                    .collect()
+            }
+        };
+        if matches!(
+            grouping,
+            Grouping::Category | Grouping::Tag | Grouping::Tree
+        ) {
+            rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        }
+        TimeReport {
+            grouping,
+            from,
+            to,
+            rows,
+            total,
+            undated,
+        }
+    }
+    /// The report as CSV, with the hours in each group to two decimal places and a final row
+    /// with the total.
+    pub fn to_csv(&self) -> String {
+        let mut result = format!("{},Hours\n", self.grouping);
+        for (name, duration) in self.rows.iter() {
+            result.push_str(&format!("{},{}\n", csv_field(name), format_hours(duration)));
+        }
+        result.push_str(&format!("Total,{}\n", format_hours(&self.total)));
+        result
+    }
+}
+fn group_by(
+    by_task: &HashMap<KanbanId, TimeDelta>,
+    groups_of: impl Fn(KanbanId) -> Vec<String>,
+) -> Vec<(String, TimeDelta)> {
+    let mut groups: HashMap<String, TimeDelta> = HashMap::new();
+    for (id, duration) in by_task.iter() {
+        for group in groups_of(*id) {
+            *groups.entry(group).or_default() += *duration;
+        }
+    }
+    groups.into_iter().collect()
+}
+/// A duration as a number of hours, to two decimal places.
+pub fn format_hours(duration: &TimeDelta) -> String {
+    format!("{:.2}", duration.num_seconds() as f64 / 3600.0)
+}
+/// Quote a field for CSV if it needs to be.
+pub fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time_tracking::TimeEntry;
+    fn log(task: &mut KanbanItem, day: NaiveDate, hours: i64) {
+        let start = day
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+            .to_utc();
+        task.time_records.entries.push((
+            TimeEntry::Concluded(start, start + TimeDelta::hours(hours)),
+            None,
+        ));
+    }
+    #[test]
+    fn test_report() {
+        // Two trees sharing task 2
+        let mut document = tests::make_document_easy(4, &[vec![2], vec![2], vec![], vec![]]);
+        let monday = NaiveDate::from_ymd_opt(2024, 5, 27).unwrap();
+        let mut shared = document.get_task(2).unwrap().clone();
+        shared.tags = vec!["a".into(), "b".into()];
+        log(&mut shared, monday, 2);
+        log(&mut shared, monday + TimeDelta::days(7), 1);
+        shared
+            .time_records
+            .entries
+            .push((TimeEntry::InstanteousDuration(TimeDelta::hours(5)), None));
+        document.replace_task(&shared);
+        let mut other = document.get_task(3).unwrap().clone();
+        log(&mut other, monday + TimeDelta::days(1), 1);
+        document.replace_task(&other);
+
+        let to = monday + TimeDelta::days(6);
+        let report = TimeReport::new(&document, monday, to, Grouping::Tree);
+        assert_eq!(report.total, TimeDelta::hours(3));
+        assert_eq!(report.undated, TimeDelta::hours(5));
+        assert_eq!(report.rows.len(), 3);
+        assert!(report.rows.iter().all(|(_, x)| *x <= TimeDelta::hours(2)));
+
+        let report = TimeReport::new(&document, monday, to, Grouping::Tag);
+        assert_eq!(report.rows[0], ("a".to_string(), TimeDelta::hours(2)));
+        assert_eq!(report.rows[1], ("b".to_string(), TimeDelta::hours(2)));
+        assert_eq!(report.total, TimeDelta::hours(3));
+
+        let report = TimeReport::new(&document, monday, to + TimeDelta::days(7), Grouping::Week);
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(
+            report.rows[0],
+            ("2024-W22".to_string(), TimeDelta::hours(3))
+        );
+        assert!(report.to_csv().ends_with("Total,4.00\n"));
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::TimeDelta;
+use chrono::{NaiveDate, NaiveTime, TimeDelta};
 
 use super::*;
 use std::collections::HashSet;
@@ -28,6 +28,31 @@ impl TimeEntry {
             Self::Started(start) => Utc::now() - start,
         }
     }
+    /// The time spent on each day in the local time zone, split at midnight.
+    ///
+    /// Durations entered by hand weren't spent at any particular time, so they aren't on any day.
+    pub fn per_day(self) -> Vec<(NaiveDate, TimeDelta)> {
+        let (start, end) = match self {
+            Self::InstanteousDuration(_) => return Vec::new(),
+            Self::Concluded(start, end) => (start, end),
+            Self::Started(start) => (start, Utc::now()),
+        };
+        let end = end.with_timezone(&Local);
+        let mut current = start.with_timezone(&Local);
+        let mut result = Vec::new();
+        while current < end {
+            let day = current.date_naive();
+            let midnight = day.succ_opt().and_then(|x| {
+                x.and_time(NaiveTime::MIN)
+                    .and_local_timezone(Local)
+                    .earliest()
+            });
+            let stop = midnight.map_or(end, |x| x.min(end));
+            result.push((day, stop - current));
+            current = stop;
+        }
+        result
+    }
     pub fn to_description(self) -> String {
         let dur = self.duration();
         match self {
@@ -95,6 +120,71 @@ impl TimeRecords {
             .rev()
             .any(|x| matches!(x.0, TimeEntry::Started(_)))
     }
+    /// When the entry still being recorded started, the latest one if there are several.
+    pub fn running_since(&self) -> Option<DateTime<Utc>> {
+        self.entries
+            .iter()
+            .filter_map(|x| match x.0 {
+                TimeEntry::Started(start) => Some(start),
+                _ => None,
+            })
+            .max()
+    }
+    /// Finish every entry still being recorded at `at`, or when it started if that's later.
+    ///
+    /// Returns true if any were recording.
+    pub fn stop_at(&mut self, at: DateTime<Utc>) -> bool {
+        let mut stopped = false;
+        for (entry, _) in self.entries.iter_mut() {
+            if let TimeEntry::Started(start) = *entry {
+                *entry = TimeEntry::Concluded(start, at.max(start));
+                stopped = true;
+            }
+        }
+        stopped
+    }
+    /// Deal with the time between `from` and `to` the user spent away while the entry being
+    /// recorded was running, which carries on running from `to`.
+    ///
+    /// Returns true if an entry was recording.
+    pub fn resolve_idle(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        resolution: IdleResolution,
+    ) -> bool {
+        let Some(index) = self
+            .entries
+            .iter()
+            .rposition(|x| matches!(x.0, TimeEntry::Started(_)))
+        else {
+            return false;
+        };
+        let TimeEntry::Started(start) = self.entries[index].0 else {
+            unreachable!()
+        };
+        let from = from.max(start);
+        let to = to.max(from);
+        let description = self.entries[index].1.clone();
+        match resolution {
+            IdleResolution::Keep => return true,
+            IdleResolution::Discard => {
+                self.entries[index].0 = TimeEntry::Concluded(start, from);
+            }
+            IdleResolution::Split => {
+                self.entries[index].0 = TimeEntry::Concluded(start, from);
+                self.entries.push((
+                    TimeEntry::Concluded(from, to),
+                    Some(match &description {
+                        Some(description) => format!("Away from: {}", description),
+                        None => "Away".into(),
+                    }),
+                ));
+            }
+        }
+        self.entries.push((TimeEntry::Started(to), description));
+        true
+    }
     /// Get the total duration of all the time records in the structure
     pub fn duration(&self) -> chrono::TimeDelta {
         self.entries
@@ -103,6 +193,121 @@ impl TimeRecords {
             .fold(chrono::TimeDelta::new(0, 0).unwrap(), |a, b| a + b)
     }
 }
+/// What to do with the time spent away while a timer was running.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IdleResolution {
+    /// Leave it out, as it wasn't spent on the task
+    Discard,
+    /// Count it as part of the entry being recorded
+    Keep,
+    /// Count it, but as an entry of its own so it can be told apart
+    Split,
+}
+/// Notices long gaps in the user's activity, such as a timer being left running overnight.
+#[derive(Clone, Debug)]
+pub struct IdleTracker {
+    /// How long without activity counts as being away, never if not given
+    threshold: Option<TimeDelta>,
+    last_active: DateTime<Utc>,
+    away: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+impl IdleTracker {
+    pub fn new(threshold: Option<TimeDelta>) -> Self {
+        IdleTracker {
+            threshold,
+            last_active: Utc::now(),
+            away: None,
+        }
+    }
+    /// Record that the user did something at `at`, noting the time they were away if it has
+    /// been longer than the threshold since they last did.
+    pub fn active_at(&mut self, at: DateTime<Utc>) {
+        if self
+            .threshold
+            .is_some_and(|threshold| at - self.last_active >= threshold)
+        {
+            self.away = Some((self.last_active, at));
+        }
+        self.last_active = self.last_active.max(at);
+    }
+    /// The last time the user was away, from when they were last active to when they came
+    /// back, if it hasn't been taken already.
+    pub fn take_away(&mut self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.away.take()
+    }
+}
+/// How long a timer can run before it has probably been forgotten about.
+pub const STALE_TIMER: TimeDelta = TimeDelta::hours(12);
+
+/// Only one task is timed at once, starting a timer stops the one running on any other task.
+impl KanbanDocument {
+    /// The task being timed and when its timer started. If timers were left running on several
+    /// tasks by an older version, the one started last.
+    pub fn active_timer(&self) -> Option<(KanbanId, DateTime<Utc>)> {
+        self.get_tasks()
+            .filter_map(|x| x.time_records.running_since().map(|start| (x.id, start)))
+            .max_by_key(|(_, start)| *start)
+    }
+    /// Start timing the task, stopping the timers on every other task.
+    pub fn start_timer(&mut self, id: KanbanId, description: Option<String>) -> Option<UndoItem> {
+        let mut task = self.get_task(id)?.clone();
+        self.begin_transaction();
+        let now = Utc::now();
+        self.stop_timers_except(Some(id), now);
+        if !task.time_records.is_recording() {
+            task.time_records
+                .entries
+                .push((TimeEntry::Started(now), description));
+            self.replace_task(&task);
+        }
+        self.commit_transaction()
+    }
+    /// Stop every timer at `at`, returning the item that undoes it if any were running.
+    pub fn stop_timers(&mut self, at: DateTime<Utc>) -> Option<UndoItem> {
+        self.begin_transaction();
+        self.stop_timers_except(None, at);
+        self.commit_transaction()
+    }
+    /// Deal with the time between `from` and `to` the user spent away while a task was timed,
+    /// returning the item that undoes it if a timer was running.
+    pub fn resolve_idle(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        resolution: IdleResolution,
+    ) -> Option<UndoItem> {
+        let (id, _) = self.active_timer()?;
+        let mut task = self.get_task(id)?.clone();
+        if resolution == IdleResolution::Keep
+            || !task.time_records.resolve_idle(from, to, resolution)
+        {
+            return None;
+        }
+        Some(self.replace_task(&task))
+    }
+    /// Stop the timer on every task but `except`, returning the ids of the tasks stopped.
+    ///
+    /// For timers started somewhere the document doesn't know about, such as an open editor.
+    pub fn stop_timers_except(
+        &mut self,
+        except: Option<KanbanId>,
+        at: DateTime<Utc>,
+    ) -> Vec<KanbanId> {
+        let running: Vec<KanbanItem> = self
+            .get_tasks()
+            .filter(|x| Some(x.id) != except && x.time_records.is_recording())
+            .cloned()
+            .collect();
+        running
+            .into_iter()
+            .map(|mut task| {
+                task.time_records.stop_at(at);
+                self.replace_task(&task);
+                task.id
+            })
+            .collect()
+    }
+}
 
 pub fn collect_child_durations(
     document: &KanbanDocument,
@@ -142,4 +347,84 @@ mod test {
         t.handle_record_request(None);
         assert_eq!(t.entries.len(), 2);
     }
+    #[test]
+    fn test_single_timer() {
+        let mut document = tests::make_document_easy(2, &[]);
+        document.start_timer(0, None).unwrap();
+        assert_eq!(document.active_timer().unwrap().0, 0);
+        let undo = document.start_timer(1, None).unwrap();
+        assert!(!document.get_task(0).unwrap().time_records.is_recording());
+        assert_eq!(document.active_timer().unwrap().0, 1);
+        // Starting the second timer and stopping the first are undone together
+        undo.apply(&mut document);
+        assert_eq!(document.active_timer().unwrap().0, 0);
+        assert!(!document.get_task(1).unwrap().time_records.is_recording());
+
+        let start = document.active_timer().unwrap().1;
+        document.stop_timers(start - TimeDelta::hours(1)).unwrap();
+        assert!(document.active_timer().is_none());
+        // Stopping before the timer started leaves an empty entry rather than a negative one
+        assert!(document
+            .get_task(0)
+            .unwrap()
+            .time_records
+            .duration()
+            .is_zero());
+        assert!(document.stop_timers(Utc::now()).is_none());
+    }
+    #[test]
+    fn test_resolve_idle() {
+        let start = Utc::now() - TimeDelta::hours(10);
+        let away = start + TimeDelta::hours(1);
+        let back = away + TimeDelta::hours(8);
+        let running = TimeRecords {
+            entries: vec![(TimeEntry::Started(start), Some("Review".into()))],
+        };
+
+        let mut records = running.clone();
+        assert!(records.resolve_idle(away, back, IdleResolution::Discard));
+        assert_eq!(records.entries[0].0, TimeEntry::Concluded(start, away));
+        assert_eq!(
+            records.entries[1],
+            (TimeEntry::Started(back), Some("Review".into()))
+        );
+
+        let mut records = running.clone();
+        records.resolve_idle(away, back, IdleResolution::Split);
+        assert_eq!(records.entries.len(), 3);
+        assert_eq!(records.entries[1].0, TimeEntry::Concluded(away, back));
+        assert!(records.is_recording());
+
+        let mut records = running.clone();
+        records.resolve_idle(away, back, IdleResolution::Keep);
+        assert_eq!(records.entries, running.entries);
+        assert!(!TimeRecords::new().resolve_idle(away, back, IdleResolution::Discard));
+
+        let mut tracker = IdleTracker::new(Some(TimeDelta::minutes(15)));
+        let now = Utc::now();
+        tracker.active_at(now + TimeDelta::minutes(10));
+        assert!(tracker.take_away().is_none());
+        tracker.active_at(now + TimeDelta::minutes(30));
+        assert_eq!(
+            tracker.take_away(),
+            Some((now + TimeDelta::minutes(10), now + TimeDelta::minutes(30)))
+        );
+        assert!(tracker.take_away().is_none());
+    }
+    #[test]
+    fn test_per_day() {
+        let start = Local.with_ymd_and_hms(2024, 5, 31, 22, 0, 0).unwrap();
+        let end = Local.with_ymd_and_hms(2024, 6, 1, 1, 30, 0).unwrap();
+        let entry = TimeEntry::Concluded(start.to_utc(), end.to_utc());
+        assert_eq!(
+            entry.per_day(),
+            vec![
+                (start.date_naive(), TimeDelta::hours(2)),
+                (end.date_naive(), TimeDelta::minutes(90)),
+            ]
+        );
+        assert!(TimeEntry::InstanteousDuration(TimeDelta::hours(1))
+            .per_day()
+            .is_empty());
+    }
 }
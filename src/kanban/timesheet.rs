@@ -0,0 +1,140 @@
+//! Exporting every time entry, for spreadsheets and calendars.
+use super::schedule::format_date;
+use super::time_report::{csv_field, format_hours};
+use super::time_tracking::TimeEntry;
+use super::*;
+
+/// Every time entry as a CSV row.
+///
+/// Times are in the local time zone. Durations entered by hand have no start or end, and
+/// entries still being recorded have no end yet, with the duration so far.
+pub fn to_csv(document: &KanbanDocument) -> String {
+    let mut result =
+        String::from("Task,Name,Category,Tags,Start,End,Duration (hours),Description\n");
+    for task in document.get_tasks() {
+        for (entry, description) in task.time_records.entries.iter() {
+            let (start, end) = match entry {
+                TimeEntry::InstanteousDuration(_) => (None, None),
+                TimeEntry::Started(start) => (Some(start), None),
+                TimeEntry::Concluded(start, end) => (Some(start), Some(end)),
+            };
+            let fields = [
+                task.id.to_string(),
+                task.name.clone(),
+                task.category.clone().unwrap_or_default(),
+                task.tags.join(";"),
+                start.map(format_date).unwrap_or_default(),
+                end.map(format_date).unwrap_or_default(),
+                format_hours(&entry.duration()),
+                description.clone().unwrap_or_default(),
+            ];
+            let fields: Vec<_> = fields.iter().map(|x| csv_field(x)).collect();
+            result.push_str(&fields.join(","));
+            result.push('\n');
+        }
+    }
+    result
+}
+/// The entries with both a start and an end as iCalendar events, named after their task.
+pub fn to_ics(document: &KanbanDocument) -> String {
+    let stamp = format_ics_time(&Utc::now());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".into(),
+        "PRODID:-//kanbanrs//Timesheet//EN".into(),
+    ];
+    for task in document.get_tasks() {
+        for (index, (entry, description)) in task.time_records.entries.iter().enumerate() {
+            let TimeEntry::Concluded(start, end) = entry else {
+                continue;
+            };
+            lines.push("BEGIN:VEVENT".into());
+            lines.push(format!(
+                "UID:kanbanrs-{}-{}-{}",
+                task.id,
+                index,
+                start.timestamp()
+            ));
+            lines.push(format!("DTSTAMP:{}", stamp));
+            lines.push(format!("DTSTART:{}", format_ics_time(start)));
+            lines.push(format!("DTEND:{}", format_ics_time(end)));
+            lines.push(format!("SUMMARY:{}", escape_ics(&task.name)));
+            if let Some(description) = description {
+                lines.push(format!("DESCRIPTION:{}", escape_ics(description)));
+            }
+            let categories: Vec<String> = task
+                .category
+                .iter()
+                .chain(task.tags.iter())
+                .map(|x| escape_ics(x))
+                .collect();
+            if !categories.is_empty() {
+                lines.push(format!("CATEGORIES:{}", categories.join(",")));
+            }
+            lines.push("END:VEVENT".into());
+        }
+    }
+    lines.push("END:VCALENDAR".into());
+    let mut result = String::new();
+    for line in lines.iter() {
+        fold_ics_line(line, &mut result);
+    }
+    result
+}
+fn format_ics_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+fn escape_ics(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+/// Write a line, split so no line is longer than the 75 bytes iCalendar allows.
+fn fold_ics_line(line: &str, result: &mut String) {
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            // Continuation lines start with a space, which counts towards their length
+            result.push_str("\r\n ");
+            length = 1;
+        }
+        result.push(character);
+        length += character.len_utf8();
+    }
+    result.push_str("\r\n");
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+    #[test]
+    fn test_exports() {
+        let mut document = tests::make_document_easy(1, &[]);
+        let mut task = document.get_task(0).unwrap().clone();
+        task.name = "Write, then \"review\"".into();
+        task.tags = vec!["billing".into()];
+        let start = Utc::now() - TimeDelta::hours(2);
+        task.time_records.entries = vec![
+            (
+                TimeEntry::Concluded(start, start + TimeDelta::minutes(90)),
+                Some("a".repeat(100)),
+            ),
+            (TimeEntry::InstanteousDuration(TimeDelta::minutes(30)), None),
+        ];
+        document.replace_task(&task);
+
+        let csv = to_csv(&document);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("0,\"Write, then \"\"review\"\"\",,billing,"));
+        assert!(lines[1].contains(",1.50,aaaa"));
+        assert!(lines[2].ends_with(",,,0.50,"));
+
+        let ics = to_ics(&document);
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("SUMMARY:Write\\, then \"review\"\r\n"));
+        assert!(ics.lines().all(|x| x.len() <= 76));
+    }
+}
@@ -26,17 +26,23 @@ impl TreeOutline {
             ..Default::default()
         }
     }
-    fn dfs(&mut self, document: &KanbanDocument, sort: ItemSort, filter: &KanbanFilter) {
+    fn dfs(&mut self, document: &KanbanDocument, sort: &ItemSort, filter: &KanbanFilter) {
         self.cache.clear();
         let mut queue: VecDeque<(KanbanId, Depth)> = VecDeque::new();
         let mut buffer: Vec<(KanbanId, Depth)> =
             self.toplevel_items.iter().map(|x| (*x, 0)).collect();
-        buffer.sort_by(|(a, _), (b, _)| {
-            sort.cmp_by(
-                document.get_task(*a).unwrap(),
-                document.get_task(*b).unwrap(),
-            )
-        });
+        // Siblings are pushed last first, so they're popped off in the order they're sorted in
+        let sort_siblings = |buffer: &mut Vec<(KanbanId, Depth)>| {
+            buffer.sort_by(|(a, _), (b, _)| {
+                sort.cmp_by(
+                    document.get_task(*a).unwrap(),
+                    document.get_task(*b).unwrap(),
+                    document,
+                )
+            });
+            buffer.reverse();
+        };
+        sort_siblings(&mut buffer);
         queue.extend(buffer.drain(..));
         while let Some((current_id, depth)) = queue.pop_back() {
             if self.exclude_completed && document.get_task(current_id).unwrap().completed.is_some()
@@ -50,16 +56,11 @@ impl TreeOutline {
             self.cache.push((current_id, depth));
 
             buffer.extend(item.child_tasks.iter().map(|x| (*x, depth + 1)));
-            buffer.sort_by(|(a, _), (b, _)| {
-                sort.cmp_by(
-                    document.get_task(*b).unwrap(),
-                    document.get_task(*a).unwrap(),
-                )
-            });
+            sort_siblings(&mut buffer);
             queue.extend(buffer.drain(..));
         }
     }
-    pub fn update(&mut self, document: &KanbanDocument, sort: ItemSort, filter: &KanbanFilter) {
+    pub fn update(&mut self, document: &KanbanDocument, sort: &ItemSort, filter: &KanbanFilter) {
         self.toplevel_items.clear();
         self.cache.clear();
         let mut children_of_something: HashSet<KanbanId> = HashSet::new();
@@ -80,6 +81,20 @@ impl TreeOutline {
     pub fn set_focus(&mut self, id: KanbanId) {
         self.focused_id = Some(id);
     }
+    /// The outline's settings, for saving as a view.
+    pub fn preset(&self) -> views::LayoutPreset {
+        views::LayoutPreset::TreeOutline {
+            focus: self.focused_id,
+            exclude_completed: self.exclude_completed,
+        }
+    }
+    pub fn from_preset(focus: Option<KanbanId>, exclude_completed: bool) -> Self {
+        TreeOutline {
+            focused_id: focus,
+            exclude_completed,
+            ..Self::new()
+        }
+    }
     pub fn show(
         &mut self,
         ui: &mut egui::Ui,
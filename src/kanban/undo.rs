@@ -1,53 +1,197 @@
+//! Undoing and redoing changes to a document.
+//!
+//! Every [`UndoItem`] can be applied to a document, which produces the item that reverses it
+//! again, so the same items serve for both undoing and redoing. [`UndoHistory`] keeps track of
+//! both directions.
 use super::*;
-#[derive(Debug)]
+use chrono::TimeDelta;
+use std::collections::VecDeque;
+#[derive(Debug, Clone)]
 pub struct CreationEvent {
     pub parent_id: Option<KanbanId>,
     pub new_task: KanbanItem,
 }
 impl CreationEvent {
-    pub fn undo(&self, document: &mut KanbanDocument) {
-        document.remove_task(&KanbanItem {
-            id: self.new_task.id,
-            ..Default::default()
-        });
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        // The task may have changed since it was created, and it needs to be restored as it is
+        // now if this is redone.
+        let task = document
+            .get_task(self.new_task.id)
+            .cloned()
+            .unwrap_or_else(|| self.new_task.clone());
+        document.remove_task(&task)
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DeletionEvent {
     pub former_item: KanbanItem,
     pub parent_ids: Vec<KanbanId>,
 }
 impl DeletionEvent {
-    pub fn undo(&self, document: &mut KanbanDocument) {
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
         document.replace_task(&self.former_item);
         for i in self.parent_ids.iter() {
             let task = document.get_task_mut(*i).unwrap();
             task.add_child(&self.former_item);
         }
+        UndoItem::Create(CreationEvent {
+            parent_id: self.parent_ids.first().copied(),
+            new_task: self.former_item.clone(),
+        })
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ModificationEvent {
     pub former_item: KanbanItem,
 }
 impl ModificationEvent {
-    pub fn undo(&self, document: &mut KanbanDocument) {
-        document.replace_task(&self.former_item);
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        document.replace_task(&self.former_item)
+    }
+}
+/// A priority was added or its value changed.
+#[derive(Debug, Clone)]
+pub struct PriorityEvent {
+    pub name: String,
+    /// `None` if the priority didn't exist before
+    pub former_value: Option<i32>,
+    /// When the change was made, as changes in quick succession are one step
+    pub time: DateTime<Utc>,
+}
+/// How soon after a change to a priority the next one has to come to be part of the same step.
+pub const PRIORITY_STEP_WINDOW: TimeDelta = TimeDelta::seconds(2);
+impl PriorityEvent {
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        match self.former_value {
+            Some(value) => document.replace_priority(&self.name, value),
+            None => document.remove_priority(&self.name),
+        }
     }
 }
-#[derive(Debug)]
+/// A category was added or its style changed.
+#[derive(Debug, Clone)]
+pub struct CategoryEvent {
+    pub name: String,
+    /// `None` if the category didn't exist before
+    pub former_style: Option<KanbanCategoryStyle>,
+}
+impl CategoryEvent {
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        match self.former_style {
+            Some(style) => document.replace_category_style(&self.name, style),
+            None => document.remove_category_style(&self.name),
+        }
+    }
+}
+/// The workflow states were changed.
+#[derive(Debug, Clone)]
+pub struct WorkflowEvent {
+    pub former_workflow: Vec<workflow::WorkflowState>,
+}
+impl WorkflowEvent {
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        document.replace_workflow(self.former_workflow.clone())
+    }
+}
+/// The saved views were changed.
+#[derive(Debug, Clone)]
+pub struct ViewsEvent {
+    pub former_views: Vec<views::SavedView>,
+}
+impl ViewsEvent {
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
+        document.replace_views(self.former_views.clone())
+    }
+}
+#[derive(Debug, Clone)]
 pub enum UndoItem {
     Create(CreationEvent),
     Delete(DeletionEvent),
     Modification(ModificationEvent),
+    Priority(PriorityEvent),
+    Category(CategoryEvent),
+    Workflow(WorkflowEvent),
+    Views(ViewsEvent),
+    /// Several changes that are undone together, in the order they were made.
+    Compound(Vec<UndoItem>),
+}
+/// Part of a document changed by an [`UndoItem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Task(KanbanId),
+    Priority(String),
+    Category(String),
+    Workflow,
+    Views,
 }
 impl UndoItem {
-    pub fn undo(&self, document: &mut KanbanDocument) {
-        println!("Undoing: {:?}", self);
+    /// Reverse the change this item records, returning the item that reverses it again.
+    pub fn apply(&self, document: &mut KanbanDocument) -> UndoItem {
         match self {
-            UndoItem::Create(ce) => ce.undo(document),
-            UndoItem::Delete(de) => de.undo(document),
-            UndoItem::Modification(me) => me.undo(document),
+            UndoItem::Create(ce) => ce.apply(document),
+            UndoItem::Delete(de) => de.apply(document),
+            UndoItem::Modification(me) => me.apply(document),
+            UndoItem::Priority(pe) => pe.apply(document),
+            UndoItem::Category(ce) => ce.apply(document),
+            UndoItem::Workflow(we) => we.apply(document),
+            UndoItem::Views(ve) => ve.apply(document),
+            // Undone last to first, so the items that reverse them end up in the order they
+            // need to be applied in, last to first again.
+            UndoItem::Compound(items) => {
+                UndoItem::Compound(items.iter().rev().map(|x| x.apply(document)).collect())
+            }
+        }
+    }
+    /// The change this item was recorded for, for showing in the history.
+    pub fn description(&self) -> &'static str {
+        match self {
+            UndoItem::Create(_) => "Created",
+            UndoItem::Delete(_) => "Deleted",
+            UndoItem::Modification(_) => "Modified",
+            UndoItem::Priority(_) => "Changed priority",
+            UndoItem::Category(_) => "Changed the style of category",
+            UndoItem::Workflow(_) => "Changed the workflow",
+            UndoItem::Views(_) => "Changed the saved views",
+            UndoItem::Compound(items) => items.first().map_or("Nothing", |x| x.description()),
+        }
+    }
+    /// What was changed, using the name a task had when the item was recorded.
+    pub fn subject(&self) -> String {
+        let task = match self {
+            UndoItem::Create(ce) => &ce.new_task,
+            UndoItem::Delete(de) => &de.former_item,
+            UndoItem::Modification(me) => &me.former_item,
+            UndoItem::Priority(pe) => return format!("'{}'", pe.name),
+            UndoItem::Category(ce) => return format!("'{}'", ce.name),
+            UndoItem::Workflow(_) | UndoItem::Views(_) => return String::new(),
+            UndoItem::Compound(items) => {
+                return match items.len() {
+                    0 => String::new(),
+                    1 => items[0].subject(),
+                    n => format!("{} and {} more", items[0].subject(), n - 1),
+                }
+            }
+        };
+        format!("'{}' ({})", task.name, task.id)
+    }
+    /// The parts of the document this item changes when applied, which are the same ones that
+    /// were changed by the edit it records.
+    pub fn touched(&self) -> Vec<Target> {
+        match self {
+            UndoItem::Create(ce) => std::iter::once(ce.new_task.id)
+                .chain(ce.parent_id)
+                .map(Target::Task)
+                .collect(),
+            UndoItem::Delete(de) => std::iter::once(de.former_item.id)
+                .chain(de.parent_ids.iter().copied())
+                .map(Target::Task)
+                .collect(),
+            UndoItem::Modification(me) => vec![Target::Task(me.former_item.id)],
+            UndoItem::Priority(pe) => vec![Target::Priority(pe.name.clone())],
+            UndoItem::Category(ce) => vec![Target::Category(ce.name.clone())],
+            UndoItem::Workflow(_) => vec![Target::Workflow],
+            UndoItem::Views(_) => vec![Target::Views],
+            UndoItem::Compound(items) => items.iter().flat_map(|x| x.touched()).collect(),
         }
     }
     pub fn merge(&self, other: &Self) -> Option<Self> {
@@ -61,7 +205,291 @@ impl UndoItem {
                 }
                 _ => None,
             },
+            // Clicking through a priority's value one step at a time is a single change
+            UndoItem::Priority(pe) => match other {
+                UndoItem::Priority(other)
+                    if pe.name == other.name && other.time - pe.time <= PRIORITY_STEP_WINDOW =>
+                {
+                    Some(UndoItem::Priority(PriorityEvent {
+                        name: pe.name.clone(),
+                        former_value: pe.former_value,
+                        time: other.time,
+                    }))
+                }
+                _ => None,
+            },
+            // As are the steps of dragging a WIP limit, so long as the states themselves stay
+            // the same
+            UndoItem::Workflow(we) => match other {
+                UndoItem::Workflow(other)
+                    if we
+                        .former_workflow
+                        .iter()
+                        .map(|x| &x.name)
+                        .eq(other.former_workflow.iter().map(|x| &x.name)) =>
+                {
+                    Some(UndoItem::Workflow(we.clone()))
+                }
+                _ => None,
+            },
+            // A compound is a finished action, so what comes after it is a change of its own
             _ => None,
         }
     }
 }
+
+/// The changes made since [`KanbanDocument::begin_transaction`].
+#[derive(Default, Debug)]
+pub(crate) struct Transaction {
+    /// How many transactions are open, as they may be nested
+    depth: usize,
+    items: Vec<UndoItem>,
+}
+impl KanbanDocument {
+    /// Start collecting changes into a single [`UndoItem`], until the matching
+    /// [`Self::commit_transaction`].
+    ///
+    /// Changes made in the meantime still return their own undo items, which should be dropped in
+    /// favour of the one returned by the commit.
+    pub fn begin_transaction(&mut self) {
+        self.transaction.get_or_insert_with(Default::default).depth += 1;
+    }
+    /// Finish a transaction, returning the item that undoes every change made during it once the
+    /// outermost transaction is finished. Returns `None` for nested transactions and when
+    /// nothing was changed.
+    pub fn commit_transaction(&mut self) -> Option<UndoItem> {
+        let transaction = self
+            .transaction
+            .as_mut()
+            .expect("commit_transaction called without begin_transaction");
+        transaction.depth -= 1;
+        if transaction.depth > 0 {
+            return None;
+        }
+        let mut items = self.transaction.take().unwrap().items;
+        match items.len() {
+            0 => None,
+            1 => items.pop(),
+            _ => Some(UndoItem::Compound(items)),
+        }
+    }
+    /// Keep a copy of the change if a transaction is open.
+    pub(crate) fn record_change(&mut self, item: UndoItem) -> UndoItem {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.items.push(item.clone());
+        }
+        item
+    }
+}
+pub struct HistoryEntry {
+    pub item: UndoItem,
+    /// When the change was made
+    pub time: DateTime<Utc>,
+    /// What the change was, which stays the same while the item flips between undoing and
+    /// redoing it
+    pub description: &'static str,
+}
+/// The changes that can be undone and redone.
+///
+/// Each entry in the undo list reverses the change after it, and each entry in the redo list
+/// repeats one that was undone. Making a new change forgets the ones that were undone.
+pub struct UndoHistory {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// The most changes kept for undoing, or no limit if `None`.
+    depth: Option<usize>,
+}
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+impl UndoHistory {
+    pub fn new(depth: Option<usize>) -> Self {
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+        }
+    }
+    /// Record a change, combining it with the previous one where that's possible.
+    pub fn record(&mut self, item: UndoItem) {
+        if let Some(last) = self.undo.back_mut() {
+            if let Some(combined) = last.item.merge(&item) {
+                last.item = combined;
+                last.time = Utc::now();
+                self.redo.clear();
+                return;
+            }
+        }
+        self.push(item);
+    }
+    /// Record a change on its own.
+    pub fn push(&mut self, item: UndoItem) {
+        self.redo.clear();
+        self.undo.push_back(HistoryEntry {
+            description: item.description(),
+            item,
+            time: Utc::now(),
+        });
+        if let Some(depth) = self.depth {
+            while self.undo.len() > depth {
+                self.undo.pop_front();
+            }
+        }
+    }
+    /// Undo the most recent change, returning the item that redoes it.
+    pub fn undo(&mut self, document: &mut KanbanDocument) -> Option<&UndoItem> {
+        let entry = self.undo.pop_back()?;
+        self.redo.push(HistoryEntry {
+            item: entry.item.apply(document),
+            ..entry
+        });
+        self.redo.last().map(|x| &x.item)
+    }
+    /// Redo the most recently undone change, returning the item that undoes it.
+    pub fn redo(&mut self, document: &mut KanbanDocument) -> Option<&UndoItem> {
+        let entry = self.redo.pop()?;
+        self.undo.push_back(HistoryEntry {
+            item: entry.item.apply(document),
+            ..entry
+        });
+        self.undo.back().map(|x| &x.item)
+    }
+    /// Undo or redo changes until `position` changes have been made, returning the parts of the
+    /// document that were touched along the way.
+    pub fn rewind_to(&mut self, document: &mut KanbanDocument, position: usize) -> Vec<Target> {
+        let mut touched = Vec::new();
+        while self.undo.len() > position {
+            touched.extend(self.undo(document).unwrap().touched());
+        }
+        while self.undo.len() < position && !self.redo.is_empty() {
+            touched.extend(self.redo(document).unwrap().touched());
+        }
+        touched
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// The changes that can be undone, oldest first.
+    pub fn past(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.undo.iter()
+    }
+    /// The changes that can be redone, in the order they would be redone.
+    pub fn future(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.redo.iter().rev()
+    }
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_undo_redo() {
+        let mut document = tests::make_document_easy(2, &[vec![1]]);
+        let mut history = UndoHistory::new(None);
+        let mut task = document.get_task(1).unwrap().clone();
+        task.name = "Renamed".into();
+        history.record(document.replace_task(&task));
+        let task = document.get_task(1).unwrap().clone();
+        history.record(document.remove_task(&task));
+        assert!(document.get_task(1).is_none());
+
+        history.undo(&mut document);
+        assert_eq!(document.get_task(1).unwrap().name, "Renamed");
+        assert!(document.get_task(0).unwrap().child_tasks.contains(&1));
+        history.undo(&mut document);
+        assert_eq!(document.get_task(1).unwrap().name, "");
+        assert!(!history.can_undo());
+
+        history.rewind_to(&mut document, 2);
+        assert!(document.get_task(1).is_none());
+        assert!(document.get_task(0).unwrap().child_tasks.is_empty());
+        history.undo(&mut document);
+        // A new change means the undone one can't be redone
+        history.record(document.replace_task(&task));
+        assert!(!history.can_redo());
+    }
+    #[test]
+    fn test_metadata() {
+        let mut document = KanbanDocument::new();
+        let mut history = UndoHistory::new(None);
+        history.record(document.replace_priority("Urgent", 20));
+        history.record(document.replace_priority("Urgent", 21));
+        history.record(document.replace_priority("High", 11));
+        let style = KanbanCategoryStyle {
+            children_inherit_category: true,
+            ..Default::default()
+        };
+        history.record(document.replace_category_style("bug", style));
+        assert_eq!(history.past().count(), 3);
+
+        history.undo(&mut document);
+        assert!(!document.categories.contains_key("bug"));
+        history.undo(&mut document);
+        assert_eq!(document.priorities["High"], 10);
+        history.undo(&mut document);
+        assert!(!document.priorities.contains_key("Urgent"));
+        history.rewind_to(&mut document, 3);
+        assert_eq!(document.priorities["Urgent"], 21);
+        assert_eq!(document.categories["bug"], style);
+
+        // A change made a while after the last is a step of its own
+        let mut later = document.replace_priority("Urgent", 22);
+        if let UndoItem::Priority(pe) = &mut later {
+            pe.time += PRIORITY_STEP_WINDOW * 2;
+        }
+        history.record(later);
+        assert_eq!(history.past().count(), 4);
+    }
+    #[test]
+    fn test_transaction() {
+        let mut document = tests::make_document_easy(1, &[]);
+        let mut history = UndoHistory::new(None);
+        document.begin_transaction();
+        let child = document.get_new_task();
+        let mut parent = document.get_task(0).unwrap().clone();
+        parent.add_child(&child);
+        document.begin_transaction();
+        document.replace_task(&parent);
+        assert!(document.commit_transaction().is_none());
+        let item = document.commit_transaction().unwrap();
+        assert!(matches!(&item, UndoItem::Compound(items) if items.len() == 2));
+        history.record(item);
+
+        history.undo(&mut document);
+        assert!(document.get_task(child.id).is_none());
+        assert!(document.get_task(0).unwrap().child_tasks.is_empty());
+        history.redo(&mut document);
+        assert!(document.get_task(child.id).is_some());
+        assert!(document
+            .get_task(0)
+            .unwrap()
+            .child_tasks
+            .contains(&child.id));
+
+        // Editing the child later is a step of its own, undone without removing the child
+        let mut renamed = document.get_task(child.id).unwrap().clone();
+        renamed.name = "Renamed".into();
+        history.record(document.replace_task(&renamed));
+        assert_eq!(history.past().count(), 2);
+        history.undo(&mut document);
+        assert!(document.get_task(child.id).is_some());
+    }
+    #[test]
+    fn test_depth() {
+        let mut document = tests::make_document_easy(4, &[]);
+        let mut history = UndoHistory::new(Some(2));
+        for id in 0..4 {
+            let task = document.get_task(id).unwrap().clone();
+            history.push(document.remove_task(&task));
+        }
+        assert_eq!(history.past().count(), 2);
+    }
+}
@@ -0,0 +1,169 @@
+//! Named combinations of filter, sort and layout, saved in the document to return to later.
+use super::filter::KanbanFilter;
+use super::sorting::ItemSort;
+use super::*;
+
+/// A layout along with the settings that belong to it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LayoutPreset {
+    Columnar,
+    Queue,
+    Search {
+        prompt: String,
+        #[serde(default)]
+        sort_first: bool,
+    },
+    Focused(Option<KanbanId>),
+    TreeOutline {
+        focus: Option<KanbanId>,
+        exclude_completed: bool,
+    },
+    Node {
+        focus: Option<KanbanId>,
+        exclude_completed: bool,
+        collapsed: Vec<KanbanId>,
+    },
+    Calendar {
+        /// Shows a week rather than a month
+        week: bool,
+    },
+}
+impl LayoutPreset {
+    /// The same preset without the tasks that are no longer in the document.
+    pub fn without_missing(&self, document: &KanbanDocument) -> Self {
+        let exists = |x: &Option<KanbanId>| x.filter(|x| document.get_task(*x).is_some());
+        match self {
+            Self::Focused(focus) => Self::Focused(exists(focus)),
+            Self::TreeOutline {
+                focus,
+                exclude_completed,
+            } => Self::TreeOutline {
+                focus: exists(focus),
+                exclude_completed: *exclude_completed,
+            },
+            Self::Node {
+                focus,
+                exclude_completed,
+                collapsed,
+            } => Self::Node {
+                focus: exists(focus),
+                exclude_completed: *exclude_completed,
+                collapsed: collapsed
+                    .iter()
+                    .copied()
+                    .filter(|x| document.get_task(*x).is_some())
+                    .collect(),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedView {
+    pub name: String,
+    pub filter: KanbanFilter,
+    pub sort: ItemSort,
+    pub layout: LayoutPreset,
+}
+impl SavedView {
+    /// The same view without the tasks that are no longer in the document, with a filter on
+    /// the tasks related to a missing one replaced by no filter at all.
+    pub fn without_missing(&self, document: &KanbanDocument) -> Self {
+        let filter = match &self.filter {
+            KanbanFilter::RelatedTo(id) if document.get_task(*id).is_none() => KanbanFilter::None,
+            filter => filter.clone(),
+        };
+        SavedView {
+            name: self.name.clone(),
+            filter,
+            sort: self.sort.clone(),
+            layout: self.layout.without_missing(document),
+        }
+    }
+}
+impl KanbanDocument {
+    pub fn views(&self) -> &[SavedView] {
+        &self.views
+    }
+    pub fn replace_views(&mut self, views: Vec<SavedView>) -> UndoItem {
+        let former = std::mem::replace(&mut self.views, views);
+        self.record_change(UndoItem::Views(undo::ViewsEvent {
+            former_views: former,
+        }))
+    }
+    /// Save the view, replacing the one with the same name if there is one.
+    pub fn save_view(&mut self, view: SavedView) -> UndoItem {
+        let mut views = self.views.clone();
+        match views.iter_mut().find(|x| x.name == view.name) {
+            Some(existing) => *existing = view,
+            None => views.push(view),
+        }
+        self.replace_views(views)
+    }
+    pub fn remove_view(&mut self, name: &str) -> Option<UndoItem> {
+        let mut views = self.views.clone();
+        let index = views.iter().position(|x| x.name == name)?;
+        views.remove(index);
+        Some(self.replace_views(views))
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sorting::{Direction, SortKey};
+    #[test]
+    fn test_views() {
+        let mut document = tests::make_document_easy(2, &[]);
+        let bugs = SavedView {
+            name: "My bugs".into(),
+            filter: KanbanFilter::query("cat:bug AND completed:none"),
+            sort: ItemSort::by(SortKey::Id, Direction::Descending),
+            layout: LayoutPreset::TreeOutline {
+                focus: Some(1),
+                exclude_completed: true,
+            },
+        };
+        document.save_view(bugs.clone());
+        let mut renamed = bugs.clone();
+        renamed.sort = ItemSort::by(SortKey::Name, Direction::Ascending);
+        let undo = document.save_view(renamed.clone());
+        assert_eq!(document.views().len(), 1);
+        assert_eq!(document.views()[0].sort, renamed.sort);
+        undo.apply(&mut document);
+        assert_eq!(document.views()[0], bugs);
+
+        // The query is stored as written, and parsed again when it's loaded
+        let text = serde_json::to_string(&document.views()[0]).unwrap();
+        let loaded: SavedView = serde_json::from_str(&text).unwrap();
+        assert_eq!(loaded, bugs);
+
+        let task = document.get_task(1).unwrap().clone();
+        document.remove_task(&task);
+        assert_eq!(
+            bugs.layout.without_missing(&document),
+            LayoutPreset::TreeOutline {
+                focus: None,
+                exclude_completed: true
+            }
+        );
+        document.remove_view("My bugs").unwrap();
+
+        // A view of the tasks related to one that's since been removed shows everything
+        let related = SavedView {
+            name: "Around 0".into(),
+            filter: KanbanFilter::RelatedTo(0),
+            sort: ItemSort::default(),
+            layout: LayoutPreset::Queue,
+        };
+        let mut document = tests::make_document_easy(3, &[vec![1]]);
+        let task = document.get_task(0).unwrap().clone();
+        document.remove_task(&task);
+        let applied = related.without_missing(&document);
+        assert_eq!(applied.filter, KanbanFilter::None);
+        assert!(document
+            .get_tasks()
+            .all(|x| applied.filter.matches(x, &document)));
+        assert!(document.views().is_empty());
+        assert!(document.remove_view("My bugs").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! User defined workflow states, shown as the columns of the columnar layout.
+//!
+//! A document without any states falls back to the columns derived from completion and
+//! dependencies: Ready, Blocked and Completed.
+//!
+//! Completion stays the authority on whether a task is done. A task's state only picks the
+//! column among those that agree with it, so marking a task completed from anywhere moves it
+//! into a completing state, and reopening it moves it back out.
+use super::*;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorkflowState {
+    pub name: String,
+    /// The most tasks that should be in this state at once
+    pub wip_limit: Option<usize>,
+    /// Tasks moved into this state are marked completed
+    pub marks_completed: bool,
+}
+impl WorkflowState {
+    pub fn new(name: &str) -> Self {
+        WorkflowState {
+            name: name.into(),
+            wip_limit: None,
+            marks_completed: false,
+        }
+    }
+}
+/// The column names used when a document doesn't define any states.
+pub const DEFAULT_COLUMNS: [&str; 3] = ["Ready", "Blocked", "Completed"];
+
+impl KanbanDocument {
+    pub fn workflow(&self) -> &[WorkflowState] {
+        &self.workflow
+    }
+    pub fn replace_workflow(&mut self, workflow: Vec<WorkflowState>) -> UndoItem {
+        let former = std::mem::replace(&mut self.workflow, workflow);
+        self.record_change(UndoItem::Workflow(undo::WorkflowEvent {
+            former_workflow: former,
+        }))
+    }
+    /// The names of the columns tasks are placed in, along with their WIP limits.
+    pub fn columns(&self) -> Vec<(&str, Option<usize>)> {
+        if self.workflow.is_empty() {
+            DEFAULT_COLUMNS.iter().map(|x| (*x, None)).collect()
+        } else {
+            self.workflow
+                .iter()
+                .map(|x| (x.name.as_str(), x.wip_limit))
+                .collect()
+        }
+    }
+    /// The index of the column the task belongs in, matching [`Self::columns`].
+    pub fn column_of(&self, task: &KanbanItem) -> usize {
+        if self.workflow.is_empty() {
+            return match self.task_status(&task.id) {
+                Status::Ready => 0,
+                Status::Blocked => 1,
+                Status::Completed => 2,
+            };
+        }
+        let completed = task.completed.is_some();
+        let agrees = |x: &WorkflowState| x.marks_completed == completed;
+        if let Some(index) = task
+            .state
+            .as_ref()
+            .and_then(|name| self.workflow.iter().position(|x| x.name == *name))
+        {
+            if agrees(&self.workflow[index]) {
+                return index;
+            }
+        }
+        match self.workflow.iter().position(agrees) {
+            Some(index) => index,
+            // Without a state that agrees, completed tasks go at the end and the rest at the
+            // start, as they would with the default columns
+            None if completed => self.workflow.len() - 1,
+            None => 0,
+        }
+    }
+}
+impl KanbanItem {
+    /// Move the task into a workflow state, marking it completed or not to match.
+    pub fn set_state(&mut self, state: Option<String>, document: &KanbanDocument) {
+        let marks_completed = state
+            .as_ref()
+            .and_then(|name| document.workflow.iter().find(|x| x.name == *name))
+            .map(|x| x.marks_completed);
+        match marks_completed {
+            Some(true) if self.completed.is_none() => self.completed = Some(Utc::now()),
+            Some(false) => self.completed = None,
+            _ => (),
+        }
+        self.state = state;
+    }
+    /// Move the task into the column at `column`, as in [`KanbanDocument::columns`].
+    ///
+    /// Without a workflow, only completion can be changed: the Completed column marks the task
+    /// completed and the others reopen it, since whether it's blocked depends on its children.
+    /// Returns whether the task changed.
+    pub fn move_to_column(&mut self, column: usize, document: &KanbanDocument) -> bool {
+        if document.workflow.is_empty() {
+            let completing = column == DEFAULT_COLUMNS.len() - 1;
+            if completing == self.completed.is_some() {
+                return false;
+            }
+            self.completed = completing.then(Utc::now);
+            return true;
+        }
+        match document.workflow.get(column) {
+            Some(state) if document.column_of(self) != column => {
+                self.set_state(Some(state.name.clone()), document);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_default_columns() {
+        let document = tests::make_document_easy(2, &[vec![1]]);
+        assert_eq!(document.columns().len(), 3);
+        assert_eq!(document.column_of(document.get_task(0).unwrap()), 1);
+        assert_eq!(document.column_of(document.get_task(1).unwrap()), 0);
+    }
+    #[test]
+    fn test_states() {
+        let mut document = tests::make_document_easy(1, &[]);
+        let done = WorkflowState {
+            marks_completed: true,
+            ..WorkflowState::new("Done")
+        };
+        document.replace_workflow(vec![
+            WorkflowState::new("Backlog"),
+            WorkflowState::new("In Progress"),
+            done,
+        ]);
+        let mut task = document.get_task(0).unwrap().clone();
+        assert_eq!(document.column_of(&task), 0);
+        task.set_state(Some("In Progress".into()), &document);
+        assert_eq!(document.column_of(&task), 1);
+        task.set_state(Some("Done".into()), &document);
+        assert!(task.completed.is_some());
+        assert_eq!(document.column_of(&task), 2);
+        // Reopening a task from elsewhere takes it out of the completing state
+        task.completed = None;
+        assert_eq!(document.column_of(&task), 0);
+        // A state that was since removed is treated as no state at all
+        task.state = Some("Review".into());
+        assert_eq!(document.column_of(&task), 0);
+    }
+    #[test]
+    fn test_move_to_column() {
+        let mut document = tests::make_document_easy(2, &[vec![1]]);
+        let mut task = document.get_task(0).unwrap().clone();
+        // Being blocked isn't up to the user
+        assert!(!task.move_to_column(0, &document));
+        assert!(task.move_to_column(2, &document));
+        assert!(task.completed.is_some());
+        assert!(!task.move_to_column(2, &document));
+        assert!(task.move_to_column(1, &document));
+        assert!(task.completed.is_none());
+
+        document.replace_workflow(vec![
+            WorkflowState::new("Backlog"),
+            WorkflowState {
+                marks_completed: true,
+                ..WorkflowState::new("Done")
+            },
+        ]);
+        assert!(!task.move_to_column(0, &document));
+        assert!(task.move_to_column(1, &document));
+        assert_eq!(task.state.as_deref(), Some("Done"));
+        assert!(task.completed.is_some());
+        assert!(!task.move_to_column(5, &document));
+    }
+}
@@ -0,0 +1,90 @@
+use super::workflow::WorkflowState;
+use super::*;
+pub struct WorkflowEditor {
+    pub name: String,
+    pub open: bool,
+}
+impl Default for WorkflowEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl WorkflowEditor {
+    pub fn new() -> Self {
+        WorkflowEditor {
+            name: String::new(),
+            open: false,
+        }
+    }
+    /// Show the WorkflowEditor
+    /// * `document` the document to operate on
+    /// * `ui` The UI instance
+    ///
+    /// returns the change made to the document, to be recorded for undoing.
+    pub fn show(&mut self, document: &mut KanbanDocument, ui: &mut egui::Ui) -> Option<UndoItem> {
+        let mut workflow = document.workflow().to_vec();
+        let mut changed = false;
+        ui.label(
+            "Each state is a column in the columnar layout. Without any states, tasks are \
+             arranged by whether they're ready, blocked or completed.",
+        );
+        ui.horizontal(|ui| {
+            ui.label("State name");
+            ui.text_edit_singleline(&mut self.name);
+            let exists = workflow.iter().any(|x| x.name == self.name);
+            if ui
+                .add_enabled(!self.name.is_empty() && !exists, egui::Button::new("Add"))
+                .clicked()
+            {
+                workflow.push(WorkflowState::new(&self.name));
+                self.name.clear();
+                changed = true;
+            }
+        });
+        let mut remove = None;
+        let mut swap = None;
+        let count = workflow.len();
+        ScrollArea::vertical().id_salt("workflow").show(ui, |ui| {
+            for (index, state) in workflow.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&state.name).strong());
+                    let mut limited = state.wip_limit.is_some();
+                    if ui.checkbox(&mut limited, "WIP limit").changed() {
+                        state.wip_limit = limited.then_some(1);
+                        changed = true;
+                    }
+                    if let Some(limit) = state.wip_limit.as_mut() {
+                        changed |= ui
+                            .add(egui::DragValue::new(limit).range(1..=usize::MAX))
+                            .changed();
+                    }
+                    changed |= ui
+                        .checkbox(&mut state.marks_completed, "Completes tasks")
+                        .on_hover_text("Tasks moved into this state are marked completed")
+                        .changed();
+                    if ui.add_enabled(index > 0, egui::Button::new("⏶")).clicked() {
+                        swap = Some((index - 1, index));
+                    }
+                    if ui
+                        .add_enabled(index + 1 < count, egui::Button::new("⏷"))
+                        .clicked()
+                    {
+                        swap = Some((index, index + 1));
+                    }
+                    if ui.button("Remove").clicked() {
+                        remove = Some(index);
+                    }
+                });
+            }
+        });
+        if let Some((a, b)) = swap {
+            workflow.swap(a, b);
+            changed = true;
+        }
+        if let Some(index) = remove {
+            workflow.remove(index);
+            changed = true;
+        }
+        changed.then(|| document.replace_workflow(workflow))
+    }
+}
@@ -0,0 +1,6 @@
+//! The document model behind kanbanrs.
+//!
+//! Tasks, filtering, sorting, undo and time tracking are all usable without the interface;
+//! depend on this crate with `default-features = false` to leave egui, eframe and rfd out.
+//! The `gui` feature adds the widgets and layouts that the kanbanrs application is built from.
+pub mod kanban;
@@ -1,13 +1,28 @@
-mod kanban;
-use chrono::Utc;
-use circular_buffer::CircularBuffer;
+use chrono::{DateTime, Local, Utc};
 use clap::*;
 use eframe::egui::{self, ComboBox, RichText, Vec2};
-use kanban::{
-    category_editor::State, editor::EditorRequest, filter::KanbanFilter, node_layout::NodeLayout,
-    priority_editor::PriorityEditor, queue_view::QueueState, search::SearchState,
-    sorting::ItemSort, tree_outline_layout::TreeOutline, undo::CreationEvent, KanbanDocument,
-    SummaryAction,
+use kanbanrs::kanban::{
+    self,
+    calendar_layout::{Calendar, CalendarSpan},
+    category_editor::State,
+    editor::EditorRequest,
+    filter::KanbanFilter,
+    integrity::Diagnostic,
+    node_layout::NodeLayout,
+    persistence::DocumentError,
+    priority_editor::PriorityEditor,
+    queue_view::QueueState,
+    recovery::{self, JournalEntry, Recovery},
+    report_window::ReportWindow,
+    search::SearchState,
+    sorting::ItemSort,
+    statistics_window::StatisticsWindow,
+    time_tracking::{IdleResolution, IdleTracker},
+    tree_outline_layout::TreeOutline,
+    undo::{CreationEvent, Target, UndoHistory},
+    views::{LayoutPreset, SavedView},
+    workflow_editor::WorkflowEditor,
+    KanbanDocument, SummaryAction,
 };
 use parking_lot::RwLock;
 use std::{
@@ -16,8 +31,10 @@ use std::{
     io::Write,
     path::PathBuf,
     sync::{mpsc, Arc},
+    time::{Duration, Instant},
 };
 mod document_layout;
+mod headless;
 use document_layout::*;
 
 struct KanbanRS {
@@ -36,11 +53,42 @@ struct KanbanRS {
     sorting_type: kanban::sorting::ItemSort,
     category_editor: kanban::category_editor::State,
     priority_editor: PriorityEditor,
+    workflow_editor: WorkflowEditor,
+    report_window: ReportWindow,
+    statistics_window: StatisticsWindow,
     modified_since_last_saved: bool,
     editor_rx: std::sync::mpsc::Receiver<EditorRequest>,
     editor_tx: std::sync::mpsc::Sender<EditorRequest>,
-    undo_buffer: CircularBuffer<35, kanban::undo::UndoItem>,
+    undo_history: UndoHistory,
+    history_open: bool,
     filter: kanban::filter::KanbanFilter,
+    /// Shown in a dialog until dismissed
+    error_message: Option<String>,
+    integrity_report: Option<IntegrityReport>,
+    /// Keeps the unsaved changes to the open file safe, if it has been saved before
+    recovery: Option<Recovery>,
+    last_autosave: Instant,
+    /// A file with changes left over from a previous session, waiting for the user to decide
+    /// whether to restore them
+    recovery_prompt: Option<PathBuf>,
+    /// When the stale timer the user chose to keep running started, so they aren't asked again
+    stale_timer_kept: Option<DateTime<Utc>>,
+    /// The time entered to stop a stale timer at
+    stale_timer_stop: String,
+    /// Shared with the editors, so activity in any window counts
+    idle: Arc<RwLock<IdleTracker>>,
+    /// The time the user was away while a task was being timed, until they say what to do with it
+    idle_prompt: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// The name to save the current view under
+    new_view_name: String,
+}
+/// How often a snapshot of a modified document is written for recovery.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Problems found in a document, shown until they are repaired or dismissed.
+struct IntegrityReport {
+    problems: Vec<Diagnostic>,
+    /// A document that couldn't be opened because of the problems, opened once it's repaired
+    unopened: Option<(PathBuf, Box<KanbanDocument>)>,
 }
 impl KanbanRS {
     fn new() -> Self {
@@ -57,14 +105,28 @@ impl KanbanRS {
             close_application: false,
             layout_cache_needs_updating: true,
             summary_actions_pending: Vec::new(),
-            sorting_type: kanban::sorting::ItemSort::None,
+            sorting_type: kanban::sorting::ItemSort::default(),
             category_editor: State::new(),
             priority_editor: PriorityEditor::new(),
+            workflow_editor: WorkflowEditor::new(),
+            report_window: ReportWindow::new(),
+            statistics_window: StatisticsWindow::new(),
             modified_since_last_saved: false,
             editor_rx: rx,
             editor_tx: tx,
-            undo_buffer: CircularBuffer::new(),
+            undo_history: UndoHistory::default(),
+            history_open: false,
             filter: KanbanFilter::None,
+            error_message: None,
+            integrity_report: None,
+            recovery: None,
+            last_autosave: Instant::now(),
+            recovery_prompt: None,
+            stale_timer_kept: None,
+            stale_timer_stop: String::new(),
+            idle: Arc::new(RwLock::new(IdleTracker::new(None))),
+            idle_prompt: None,
+            new_view_name: String::new(),
         }
     }
 }
@@ -75,6 +137,7 @@ enum StartupLayout {
     TreeOutline,
     Queue,
     Search,
+    Calendar,
 }
 impl std::fmt::Display for StartupLayout {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -84,13 +147,12 @@ impl std::fmt::Display for StartupLayout {
 impl From<StartupLayout> for KanbanDocumentLayout {
     fn from(value: StartupLayout) -> Self {
         match value {
-            StartupLayout::Column => {
-                KanbanDocumentLayout::Columnar([Vec::new(), Vec::new(), Vec::new()])
-            }
+            StartupLayout::Column => KanbanDocumentLayout::Columnar(Vec::new()),
             StartupLayout::Node => KanbanDocumentLayout::NodeLayout(NodeLayout::new()),
             StartupLayout::Queue => KanbanDocumentLayout::Queue(QueueState::new()),
             StartupLayout::Search => KanbanDocumentLayout::Search(SearchState::new()),
             StartupLayout::TreeOutline => KanbanDocumentLayout::TreeOutline(TreeOutline::new()),
+            StartupLayout::Calendar => KanbanDocumentLayout::Calendar(Calendar::new()),
         }
     }
 }
@@ -99,6 +161,16 @@ struct KanbanArgs {
     filename: Option<String>,
     #[arg(short,long,value_enum,default_value_t=StartupLayout::Column)]
     default_view: StartupLayout,
+    /// The most changes that can be undone, unlimited if not given
+    #[arg(long)]
+    undo_depth: Option<usize>,
+    /// How many minutes without any input, while a task is being timed, before asking what to do
+    /// with the time away. Never asks if 0
+    #[arg(long, default_value_t = 15)]
+    idle_minutes: i64,
+    /// Operate on the document without opening a window
+    #[command(subcommand)]
+    command: Option<headless::Command>,
 }
 
 fn main() {
@@ -107,7 +179,18 @@ fn main() {
         viewport: egui::ViewportBuilder::default().with_inner_size([640.0, 240.0]),
         ..Default::default()
     };
-    let args = KanbanArgs::parse();
+    let mut args = KanbanArgs::parse();
+    if let Some(command) = args.command.take() {
+        let Some(filename) = args.filename else {
+            eprintln!("A document must be given to operate on");
+            std::process::exit(2);
+        };
+        if let Err(x) = headless::run(&PathBuf::from(filename), command) {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return;
+    }
     let app = KanbanRS::from_args(args);
 
     if let Err(x) = eframe::run_native("KanbanRS", options, Box::new(|_cc| Ok(Box::new(app)))) {
@@ -128,11 +211,13 @@ impl eframe::App for KanbanRS {
                     |ctx, _class| {
                         egui::CentralPanel::default().show(ctx, |ui| {
                             ui.label("You may lose information if you don't save, do you want to?");
-                            if ui.button("Save").clicked() {
-                                self.save_file(false);
+                            if ui.button("Save").clicked() && self.save_file(false) {
                                 confirmed = true;
                             }
                             if ui.button("Don't save").clicked() {
+                                if let Some(recovery) = &self.recovery {
+                                    recovery.discard();
+                                }
                                 confirmed = true;
                             }
                             if ui.button("Cancel").clicked() {
@@ -149,6 +234,16 @@ impl eframe::App for KanbanRS {
                 return;
             }
         }
+        if self.modified_since_last_saved {
+            if self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+                if let Some(recovery) = &self.recovery {
+                    recovery.snapshot(self.document.read().clone());
+                }
+                self.last_autosave = Instant::now();
+            }
+            // Make sure the snapshot is taken even if nothing else happens in the meantime
+            ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+        }
         if self.layout_cache_needs_updating {
             self.current_layout.update_cache(
                 &self.document.read(),
@@ -204,6 +299,13 @@ impl eframe::App for KanbanRS {
             })
         });
         self.hovered_task = None;
+        self.show_error_dialog(ctx);
+        self.show_integrity_dialog(ctx);
+        self.show_recovery_dialog(ctx);
+        self.check_idle(ctx);
+        self.show_idle_dialog(ctx);
+        self.show_stale_timer_dialog(ctx);
+        self.show_history(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
@@ -221,7 +323,7 @@ impl eframe::App for KanbanRS {
                             .add_filter("Kanban", &["kan"])
                             .pick_file();
                         if let Some(filename) = filename {
-                            self.open_file(&filename);
+                            self.open_file_or_report(&filename);
                         }
                         self.current_layout.update_cache(
                             &self.document.read(),
@@ -235,7 +337,7 @@ impl eframe::App for KanbanRS {
                         for i in self.read_recents() {
                             let s: String = String::from(i.to_str().unwrap());
                             if fs::exists(&s).is_ok_and(|x| x) && ui.button(&s).clicked() {
-                                self.open_file(&i);
+                                self.open_file_or_report(&i);
                                 ui.close_menu();
                                 self.layout_cache_needs_updating = true;
                             }
@@ -244,17 +346,45 @@ impl eframe::App for KanbanRS {
                     if ui.button("Export to graphviz").clicked() {
                         self.write_dot();
                     }
+                    ui.menu_button("Export timesheet", |ui| {
+                        if ui.button("CSV").clicked() {
+                            let csv = kanban::timesheet::to_csv(&self.document.read());
+                            self.write_export("CSV", "csv", &csv);
+                            ui.close_menu();
+                        }
+                        if ui.button("iCalendar").clicked() {
+                            let ics = kanban::timesheet::to_ics(&self.document.read());
+                            self.write_export("iCalendar", "ics", &ics);
+                            ui.close_menu();
+                        }
+                    });
+                    if ui.button("Time report").clicked() {
+                        self.report_window.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Statistics").clicked() {
+                        self.statistics_window.open = true;
+                        ui.close_menu();
+                    }
                     if ui.button("Quit").clicked() {
                         self.close_application = true;
                     }
                 });
                 ui.menu_button("Edit", |ui| {
-                    ui.add_enabled_ui(!self.undo_buffer.is_empty(), |ui| {
+                    ui.add_enabled_ui(self.undo_history.can_undo(), |ui| {
                         if ui.button("Undo").clicked() {
                             self.undo();
-                            self.layout_cache_needs_updating = true;
                         }
                     });
+                    ui.add_enabled_ui(self.undo_history.can_redo(), |ui| {
+                        if ui.button("Redo").clicked() {
+                            self.redo();
+                        }
+                    });
+                    if ui.button("History").clicked() {
+                        self.history_open = true;
+                        ui.close_menu();
+                    }
                     if ui.button("Category style editor").clicked() {
                         self.category_editor.open = true;
                         ui.close_menu();
@@ -263,7 +393,19 @@ impl eframe::App for KanbanRS {
                         self.priority_editor.open = true;
                         ui.close_menu();
                     }
+                    if ui.button("Workflow editor").clicked() {
+                        self.workflow_editor.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Check document").clicked() {
+                        self.integrity_report = Some(IntegrityReport {
+                            problems: self.document.read().validate(),
+                            unopened: None,
+                        });
+                        ui.close_menu();
+                    }
                 });
+                ui.menu_button("Views", |ui| self.show_views_menu(ui));
             });
             ui.horizontal(|ui| {
                 ui.label(RichText::new("Layout"));
@@ -318,15 +460,23 @@ impl eframe::App for KanbanRS {
                         .clicked()
                         .then(|| {
                             self.layout_cache_needs_updating = true;
-                        })
+                        });
+                        if ui
+                            .selectable_value(
+                                &mut self.current_layout,
+                                KanbanDocumentLayout::Calendar(Calendar::new()),
+                                "Calendar",
+                            )
+                            .clicked()
+                        {
+                            self.layout_cache_needs_updating = true;
+                        }
                     });
-                if let KanbanDocumentLayout::Search(_) = self.current_layout {
-                } else {
-                    self.layout_cache_needs_updating |= self.sorting_type.combobox(ui);
-                }
+                self.layout_cache_needs_updating |= self.sorting_type.combobox(ui);
                 if self.filter.show_ui(ui, &self.document.read()).changed() {
                     self.layout_cache_needs_updating |= true;
                 }
+                self.show_timer_indicator(ui);
             });
             ui.horizontal(|ui| {
                 ui.text_edit_singleline(&mut self.task_name);
@@ -334,11 +484,13 @@ impl eframe::App for KanbanRS {
                     let mut document = self.document.write();
                     let thing = document.get_new_task_mut();
                     thing.name = self.task_name.clone();
-                    self.undo_buffer
-                        .push_back(kanban::undo::UndoItem::Create(CreationEvent {
-                            new_task: thing.clone(),
-                            parent_id: None,
-                        }));
+                    let undo = kanban::undo::UndoItem::Create(CreationEvent {
+                        new_task: thing.clone(),
+                        parent_id: None,
+                    });
+                    drop(document);
+                    self.journal(&undo.touched());
+                    self.undo_history.push(undo);
                     self.layout_cache_needs_updating = true;
                     self.modified_since_last_saved = true;
                     self.current_layout.inform_of_new_items();
@@ -362,6 +514,13 @@ impl eframe::App for KanbanRS {
             } else if let KanbanDocumentLayout::NodeLayout(nl) = &mut self.current_layout {
                 self.layout_cache_needs_updating |=
                     nl.show(&self.document.read(), ui, &mut self.summary_actions_pending);
+            } else if let KanbanDocumentLayout::Calendar(calendar) = &mut self.current_layout {
+                self.layout_cache_needs_updating |= calendar.show(
+                    ui,
+                    &self.document.read(),
+                    &mut self.summary_actions_pending,
+                    &mut self.hovered_task,
+                );
             } else {
                 self.layout_queue(ui);
             }
@@ -371,8 +530,10 @@ impl eframe::App for KanbanRS {
                 .filter(|editor| !editor.read().open)
                 .for_each(|editor| {
                     if !editor.read().cancelled {
-                        let undo = self.document.write().replace_task(&editor.read().item_copy);
+                        // Creates the next occurrence if this completes a recurring task
+                        let undo = self.document.write().update_task(&editor.read().item_copy);
                         undo_items.push(undo);
+                        self.current_layout.inform_of_new_items();
                         self.layout_cache_needs_updating = true;
                         self.modified_since_last_saved = true;
                     }
@@ -383,6 +544,7 @@ impl eframe::App for KanbanRS {
                 let viewport_id = ui.ctx().viewport_id();
                 let document = self.document.clone();
                 let editor = editor.clone();
+                let idle = self.idle.clone();
                 let id = editor.read().item_copy.id;
                 let window_title = format!("Editing '{}'", editor.read().item_copy.name);
                 ui.ctx().show_viewport_deferred(
@@ -391,6 +553,9 @@ impl eframe::App for KanbanRS {
                         .with_window_type(egui::X11WindowType::Dialog)
                         .with_title(&window_title),
                     move |ctx, _class| {
+                        if ctx.input(|i| !i.events.is_empty()) {
+                            idle.write().active_at(Utc::now());
+                        }
                         egui::CentralPanel::default().show(ctx, |ui| {
                             if kanban::editor::editor(
                                 ui,
@@ -424,12 +589,17 @@ impl eframe::App for KanbanRS {
                                     name,
                                     style,
                                 ) => {
-                                    self.document.write().replace_category_style(&name, style);
+                                    let undo =
+                                        self.document.write().replace_category_style(&name, style);
+                                    self.record_undo(undo);
                                     self.modified_since_last_saved = true;
                                 }
                                 kanban::category_editor::EditorAction::ApplyStyle(name, style) => {
-                                    self.document.write().replace_category_style(&name, style);
+                                    let undo =
+                                        self.document.write().replace_category_style(&name, style);
+                                    self.record_undo(undo);
                                     self.modified_since_last_saved = true;
+                                    self.layout_cache_needs_updating = true;
                                 }
                                 kanban::category_editor::EditorAction::Nothing => (),
                             }
@@ -450,8 +620,12 @@ impl eframe::App for KanbanRS {
                     egui::ViewportBuilder::default(),
                     |ctx, _class| {
                         egui::CentralPanel::default().show(ctx, |ui| {
-                            self.layout_cache_needs_updating |=
-                                self.priority_editor.show(&mut self.document.write(), ui);
+                            let changes = self.priority_editor.show(&mut self.document.write(), ui);
+                            if !changes.is_empty() {
+                                self.layout_cache_needs_updating = true;
+                                self.modified_since_last_saved = true;
+                            }
+                            changes.into_iter().for_each(|x| self.record_undo(x));
                         });
                         if ctx.input(|i| i.viewport().close_requested()) {
                             self.priority_editor.open = false;
@@ -459,6 +633,56 @@ impl eframe::App for KanbanRS {
                     },
                 );
             }
+            if self.workflow_editor.open {
+                ui.ctx().show_viewport_immediate(
+                    egui::ViewportId::from_hash_of("Workflow Editor"),
+                    egui::ViewportBuilder::default().with_title("Workflow editor"),
+                    |ctx, _class| {
+                        egui::CentralPanel::default().show(ctx, |ui| {
+                            let change = self.workflow_editor.show(&mut self.document.write(), ui);
+                            if let Some(change) = change {
+                                self.layout_cache_needs_updating = true;
+                                self.modified_since_last_saved = true;
+                                self.record_undo(change);
+                            }
+                        });
+                        if ctx.input(|i| i.viewport().close_requested()) {
+                            self.workflow_editor.open = false;
+                        }
+                    },
+                );
+            }
+            if self.report_window.open {
+                ui.ctx().show_viewport_immediate(
+                    egui::ViewportId::from_hash_of("Time Report"),
+                    egui::ViewportBuilder::default().with_title("Time report"),
+                    |ctx, _class| {
+                        egui::CentralPanel::default().show(ctx, |ui| {
+                            let export = self.report_window.show(&self.document.read(), ui);
+                            if let Some(report) = export {
+                                self.write_export("CSV", "csv", &report.to_csv());
+                            }
+                        });
+                        if ctx.input(|i| i.viewport().close_requested()) {
+                            self.report_window.open = false;
+                        }
+                    },
+                );
+            }
+            if self.statistics_window.open {
+                ui.ctx().show_viewport_immediate(
+                    egui::ViewportId::from_hash_of("Statistics"),
+                    egui::ViewportBuilder::default().with_title("Statistics"),
+                    |ctx, _class| {
+                        egui::CentralPanel::default().show(ctx, |ui| {
+                            self.statistics_window.show(&self.document.read(), ui);
+                        });
+                        if ctx.input(|i| i.viewport().close_requested()) {
+                            self.statistics_window.open = false;
+                        }
+                    },
+                );
+            }
         });
     }
 }
@@ -466,8 +690,25 @@ impl eframe::App for KanbanRS {
 impl KanbanRS {
     fn from_args(args: KanbanArgs) -> Self {
         let mut result = KanbanRS::new();
+        result.undo_history = UndoHistory::new(args.undo_depth);
+        result.idle = Arc::new(RwLock::new(IdleTracker::new(
+            (args.idle_minutes > 0).then(|| chrono::TimeDelta::minutes(args.idle_minutes)),
+        )));
         if let Some(filename) = args.filename {
-            result.open_file(&PathBuf::from(filename));
+            match result.open_file(&PathBuf::from(&filename)) {
+                Ok(()) => (),
+                // This can be repaired from the interface
+                Err(DocumentError::Integrity { problems, document }) => {
+                    result.integrity_report = Some(IntegrityReport {
+                        problems,
+                        unopened: Some((PathBuf::from(&filename), document)),
+                    });
+                }
+                Err(x) => {
+                    eprintln!("Could not open '{}': {}", filename, x);
+                    std::process::exit(1);
+                }
+            }
         }
         result.current_layout = args.default_view.into();
         result
@@ -484,19 +725,21 @@ impl KanbanRS {
                 self.open_editors.push(Arc::new(RwLock::new(editor)));
             }
             SummaryAction::CreateChildOf(id) => {
-                let (child_creation, new_task, mut task_copy) = {
+                let (undo, new_task) = {
                     let mut document = self.document.write();
+                    // Undoing only one half would leave the parent depending on a missing task
+                    document.begin_transaction();
                     let mut new_task = document.get_new_task();
-                    let task_copy = document.get_task(*id).unwrap().clone();
+                    let mut task_copy = document.get_task(*id).unwrap().clone();
                     new_task.inherit(&task_copy, &document);
-                    (document.replace_task(&new_task), new_task, task_copy)
+                    document.replace_task(&new_task);
+                    task_copy.add_child(&new_task);
+                    document.replace_task(&task_copy);
+                    (document.commit_transaction().unwrap(), new_task)
                 };
 
-                task_copy.add_child(&new_task);
                 let editor = kanban::editor::state_from(&new_task, self.editor_tx.clone());
-                self.undo_buffer
-                    .push_back(self.document.write().replace_task(&task_copy));
-                self.record_undo(child_creation);
+                self.record_undo(undo);
                 self.open_editors.push(Arc::new(RwLock::new(editor)));
 
                 self.layout_cache_needs_updating = true;
@@ -504,20 +747,23 @@ impl KanbanRS {
                 self.current_layout.inform_of_new_items();
             }
             SummaryAction::MarkCompleted(id) => {
-                let (new, mut task) = {
-                    let document = self.document.read();
-                    let task = document.get_task(*id).unwrap().clone();
-                    (
-                        match task.completed {
-                            Some(_) => None,
-                            None => Some(Utc::now()),
-                        },
-                        task,
-                    )
+                let undo = {
+                    let mut document = self.document.write();
+                    let mut task = document.get_task(*id).unwrap().clone();
+                    if task.completed.is_some() {
+                        task.completed = None;
+                        Some(document.replace_task(&task))
+                    } else {
+                        // Creates the next occurrence of recurring tasks
+                        document.complete_task(*id)
+                    }
                 };
-                task.completed = new;
-                let undo = self.document.write().replace_task(&task);
-                self.record_undo(undo);
+                if let Some(undo) = undo {
+                    self.record_undo(undo);
+                    // So the next autosave snapshot has the new occurrence as well
+                    self.modified_since_last_saved = true;
+                }
+                self.current_layout.inform_of_new_items();
                 self.layout_cache_needs_updating = true;
             }
             SummaryAction::FocusOn(id) => {
@@ -552,6 +798,24 @@ impl KanbanRS {
                 self.layout_cache_needs_updating = true;
                 self.modified_since_last_saved = true;
             }
+            SummaryAction::MoveToColumn(id, column) => {
+                let undoitem = {
+                    let mut document = self.document.write();
+                    let mut task = document.get_task(*id).unwrap().clone();
+                    // Moving into a completing column creates the next occurrence of recurring
+                    // tasks
+                    task.move_to_column(*column, &document)
+                        .then(|| document.update_task(&task))
+                };
+                if let Some(item) = undoitem {
+                    self.record_undo(item);
+                    self.layout_cache_needs_updating = true;
+                    self.modified_since_last_saved = true;
+                }
+            }
+            SummaryAction::ToggleTimer(id) => {
+                self.toggle_timer(*id, None);
+            }
             SummaryAction::UpdateLayout => {
                 self.layout_cache_needs_updating = true;
             }
@@ -596,27 +860,92 @@ impl KanbanRS {
                 self.current_layout.inform_of_new_items();
             }
             kanban::editor::EditorRequest::UpdateItem(item) => {
-                let undo = self.document.write().replace_task(item);
+                let undo = self.document.write().update_task(item);
                 self.record_undo(undo);
+                // Completing a recurring task moved its rule to the next occurrence, which the
+                // editor's copy would otherwise put back
+                if let Some(task) = self.document.read().get_task(item.id) {
+                    for editor in self.open_editors.iter() {
+                        let mut editor = editor.write();
+                        if editor.item_copy.id == item.id {
+                            editor.item_copy = task.clone();
+                        }
+                    }
+                }
+                self.current_layout.inform_of_new_items();
                 self.modified_since_last_saved = true;
                 self.layout_cache_needs_updating = true;
             }
+            kanban::editor::EditorRequest::ToggleTimer(id, description) => {
+                self.toggle_timer(*id, description.take());
+            }
             _ => {}
         }
     }
 }
 
 impl KanbanRS {
+    /// Save the current filter, sort and layout as a view, or go back to one saved before.
+    fn show_views_menu(&mut self, ui: &mut egui::Ui) {
+        let views = self.document.read().views().to_vec();
+        if views.is_empty() {
+            ui.label(RichText::new("No saved views").weak());
+        }
+        for view in views.iter() {
+            ui.horizontal(|ui| {
+                if ui.button(&view.name).clicked() {
+                    self.apply_view(view);
+                    ui.close_menu();
+                }
+                if ui.small_button("🗑").on_hover_text("Remove").clicked() {
+                    let undo = self.document.write().remove_view(&view.name);
+                    if let Some(undo) = undo {
+                        self.record_undo(undo);
+                        self.modified_since_last_saved = true;
+                    }
+                }
+            });
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_view_name)
+                .on_hover_text("Saving under the name of an existing view replaces it");
+            let name = self.new_view_name.trim().to_owned();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save current view"))
+                .clicked()
+            {
+                let undo = self.document.write().save_view(SavedView {
+                    name,
+                    filter: self.filter.clone(),
+                    sort: self.sorting_type.clone(),
+                    layout: self.current_layout.preset(),
+                });
+                self.record_undo(undo);
+                self.modified_since_last_saved = true;
+                self.new_view_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+    fn apply_view(&mut self, view: &SavedView) {
+        // The view may refer to tasks that were removed after it was saved
+        let view = view.without_missing(&self.document.read());
+        self.filter = view.filter.clone();
+        self.sorting_type = view.sort.clone();
+        self.current_layout =
+            KanbanDocumentLayout::from_preset(&view.layout, &self.document.read());
+        self.layout_cache_needs_updating = true;
+    }
     #[inline]
     fn record_undo(&mut self, item: kanban::undo::UndoItem) {
-        if let Some(i) = self.undo_buffer.back_mut() {
-            if let Some(combined) = i.merge(&item) {
-                *i = combined;
-            } else {
-                self.undo_buffer.push_back(item);
-            }
-        } else {
-            self.undo_buffer.push_back(item);
+        self.journal(&item.touched());
+        self.undo_history.record(item);
+    }
+    /// Record the current state of tasks changed by an edit in the recovery journal.
+    fn journal(&self, touched: &[Target]) {
+        if let Some(recovery) = &self.recovery {
+            recovery.record(JournalEntry::capture(&self.document.read(), touched));
         }
     }
     fn get_recents_file(&self) -> Option<PathBuf> {
@@ -689,11 +1018,394 @@ impl KanbanRS {
             std::process::abort();
         }
     }
-    fn open_file(&mut self, path: &PathBuf) {
-        let file = fs::File::open(path).unwrap();
-        *self.document.write() = serde_json::from_reader(file).unwrap();
+    fn open_file(&mut self, path: &PathBuf) -> Result<(), DocumentError> {
+        let document = kanban::persistence::open(path)?;
+        self.replace_document(path, document);
+        Ok(())
+    }
+    fn replace_document(&mut self, path: &PathBuf, document: KanbanDocument) {
+        *self.document.write() = document;
         self.open_editors.clear();
         self.save_file_name = Some(path.into());
+        self.modified_since_last_saved = false;
+        self.undo_history.clear();
+        if recovery::has_unsaved_changes(path) {
+            self.recovery_prompt = Some(path.clone());
+        }
+        self.recovery = Some(Recovery::new(path));
+    }
+    fn show_recovery_dialog(&mut self, ctx: &egui::Context) {
+        let mut restore = false;
+        let mut dismissed = false;
+        if let Some(path) = &self.recovery_prompt {
+            egui::Window::new("Unsaved changes found")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "'{}' has changes from a previous session that were never saved, \
+                         most likely because kanbanrs closed unexpectedly.",
+                        path.display()
+                    ));
+                    ui.horizontal(|ui| {
+                        restore = ui.button("Restore").clicked();
+                        dismissed = ui.button("Discard").clicked();
+                    });
+                });
+        }
+        if restore {
+            let path = self.recovery_prompt.take().unwrap();
+            match recovery::recover(&path) {
+                Ok(document) => {
+                    *self.document.write() = document;
+                    self.undo_history.clear();
+                    self.modified_since_last_saved = true;
+                    self.layout_cache_needs_updating = true;
+                }
+                Err(x) => {
+                    self.error_message = Some(format!(
+                        "Could not restore the changes to '{}'\n\n{}",
+                        path.display(),
+                        x
+                    ));
+                }
+            }
+        } else if dismissed {
+            self.recovery_prompt = None;
+            if let Some(recovery) = &self.recovery {
+                recovery.discard();
+            }
+        }
+    }
+    /// Start timing the task, stopping any other timer, or stop it if it's the one running.
+    fn toggle_timer(&mut self, id: kanban::KanbanId, description: Option<String>) {
+        let now = Utc::now();
+        let undo = {
+            let mut document = self.document.write();
+            let Some(task) = document.get_task(id) else {
+                return;
+            };
+            if task.time_records.is_recording() {
+                document.stop_timers(now)
+            } else {
+                document.start_timer(id, description)
+            }
+        };
+        if let Some(undo) = undo {
+            self.record_undo(undo);
+            self.timers_changed(now);
+        }
+    }
+    /// Stop the running timer at `at`.
+    fn stop_timer(&mut self, at: DateTime<Utc>) {
+        let undo = self.document.write().stop_timers(at);
+        if let Some(undo) = undo {
+            self.record_undo(undo);
+            self.timers_changed(at);
+        }
+    }
+    /// Bring the open editors' copies of the tasks in line with the timers in the document, so
+    /// accepting an editor doesn't restart a timer that has since been stopped.
+    fn timers_changed(&mut self, at: DateTime<Utc>) {
+        let document = self.document.read();
+        for editor in self.open_editors.iter() {
+            let mut editor = editor.write();
+            let Some(task) = document.get_task(editor.item_copy.id) else {
+                continue;
+            };
+            let records = &mut editor.item_copy.time_records;
+            match (task.time_records.entries.last(), records.is_recording()) {
+                (Some(running @ (kanban::time_tracking::TimeEntry::Started(_), _)), false) => {
+                    records.entries.push(running.clone())
+                }
+                (_, true) if !task.time_records.is_recording() => {
+                    records.stop_at(at);
+                }
+                _ => (),
+            }
+        }
+        drop(document);
+        self.modified_since_last_saved = true;
+        self.layout_cache_needs_updating = true;
+    }
+    /// The task being timed and how long for, with a button to stop it, on the menu bar.
+    fn show_timer_indicator(&mut self, ui: &mut egui::Ui) {
+        let running = {
+            let document = self.document.read();
+            document
+                .active_timer()
+                .and_then(|(id, start)| Some((document.get_task(id)?.name.clone(), start)))
+        };
+        let Some((name, start)) = running else {
+            return;
+        };
+        let elapsed = (Utc::now() - start).max(chrono::TimeDelta::zero());
+        ui.separator();
+        ui.label(
+            RichText::new(format!(
+                "⏱ {} {}:{:02}:{:02}",
+                name,
+                elapsed.num_hours(),
+                elapsed.num_minutes() % 60,
+                elapsed.num_seconds() % 60
+            ))
+            .strong(),
+        )
+        .on_hover_text(format!("Started {}", kanban::schedule::format_date(&start)));
+        if ui.button("⏹").on_hover_text("Stop the timer").clicked() {
+            self.stop_timer(Utc::now());
+        }
+        ui.ctx().request_repaint_after(Duration::from_secs(1));
+    }
+    /// Notice the user coming back after being away while a task was being timed.
+    fn check_idle(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.events.is_empty()) {
+            self.idle.write().active_at(Utc::now());
+        }
+        let Some((from, to)) = self.idle.write().take_away() else {
+            return;
+        };
+        if let Some((_, start)) = self.document.read().active_timer() {
+            if start < to && self.idle_prompt.is_none() {
+                self.idle_prompt = Some((from.max(start), to));
+            }
+        }
+    }
+    /// Ask whether the time the user was away should count towards the running timer.
+    fn show_idle_dialog(&mut self, ctx: &egui::Context) {
+        let Some((from, to)) = self.idle_prompt else {
+            return;
+        };
+        let Some((id, _)) = self.document.read().active_timer() else {
+            // The timer was stopped some other way
+            self.idle_prompt = None;
+            return;
+        };
+        let name = self
+            .document
+            .read()
+            .get_task(id)
+            .map(|x| x.name.clone())
+            .unwrap_or_default();
+        let away = to - from;
+        let mut resolution = None;
+        egui::Window::new("Welcome back")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "You were away from {} to {}, {}h {}m, while '{}' was being timed.",
+                    kanban::schedule::format_date(&from),
+                    kanban::schedule::format_date(&to),
+                    away.num_hours(),
+                    away.num_minutes() % 60,
+                    name
+                ));
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Discard it")
+                        .on_hover_text("Leave the time away out of the timer")
+                        .clicked()
+                    {
+                        resolution = Some(IdleResolution::Discard);
+                    }
+                    if ui
+                        .button("Keep it")
+                        .on_hover_text("Count the time away as part of the timer")
+                        .clicked()
+                    {
+                        resolution = Some(IdleResolution::Keep);
+                    }
+                    if ui
+                        .button("Log it separately")
+                        .on_hover_text("Count the time away as an entry of its own")
+                        .clicked()
+                    {
+                        resolution = Some(IdleResolution::Split);
+                    }
+                });
+            });
+        let Some(resolution) = resolution else {
+            return;
+        };
+        self.idle_prompt = None;
+        let undo = self.document.write().resolve_idle(from, to, resolution);
+        if let Some(undo) = undo {
+            self.record_undo(undo);
+            for editor in self.open_editors.iter() {
+                let mut editor = editor.write();
+                if editor.item_copy.id == id {
+                    editor
+                        .item_copy
+                        .time_records
+                        .resolve_idle(from, to, resolution);
+                }
+            }
+            self.modified_since_last_saved = true;
+            self.layout_cache_needs_updating = true;
+        }
+    }
+    /// Ask what to do about a timer that has run for so long it was probably forgotten.
+    fn show_stale_timer_dialog(&mut self, ctx: &egui::Context) {
+        let Some((id, start)) = self.document.read().active_timer() else {
+            return;
+        };
+        // Coming back after a night away asks about the time away first
+        if Utc::now() - start < kanban::time_tracking::STALE_TIMER
+            || self.stale_timer_kept == Some(start)
+            || self.idle_prompt.is_some()
+        {
+            return;
+        }
+        let name = self
+            .document
+            .read()
+            .get_task(id)
+            .map(|x| x.name.clone())
+            .unwrap_or_default();
+        let mut stop_at = None;
+        egui::Window::new("Timer still running")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The timer on '{}' has been running since {}.",
+                    name,
+                    kanban::schedule::format_date(&start)
+                ));
+                if ui.button("Stop it now").clicked() {
+                    stop_at = Some(Utc::now());
+                }
+                ui.horizontal(|ui| {
+                    let parsed = kanban::schedule::parse_date(
+                        &self.stale_timer_stop,
+                        kanban::schedule::end_of_day(),
+                    );
+                    // A bare date means the end of that day, which for today is still to come
+                    let problem = match parsed {
+                        None => Some("Enter when to stop it"),
+                        Some(x) if x < start => Some("That's before the timer started"),
+                        Some(x) if x > Utc::now() => Some("That hasn't happened yet"),
+                        Some(_) => None,
+                    };
+                    if ui
+                        .add_enabled(problem.is_none(), egui::Button::new("Stop it at"))
+                        .on_disabled_hover_text(problem.unwrap_or_default())
+                        .clicked()
+                    {
+                        stop_at = parsed;
+                    }
+                    ui.text_edit_singleline(&mut self.stale_timer_stop)
+                        .on_hover_text("Like 2024-05-31 17:30");
+                });
+                if ui.button("Keep it running").clicked() {
+                    self.stale_timer_kept = Some(start);
+                }
+            });
+        if let Some(at) = stop_at {
+            self.stale_timer_stop.clear();
+            self.stop_timer(at);
+        }
+    }
+    fn open_file_or_report(&mut self, path: &PathBuf) {
+        match self.open_file(path) {
+            Ok(()) => (),
+            Err(DocumentError::Integrity { problems, document }) => {
+                self.integrity_report = Some(IntegrityReport {
+                    problems,
+                    unopened: Some((path.clone(), document)),
+                });
+            }
+            Err(x) => {
+                self.error_message = Some(format!("Could not open '{}'\n\n{}", path.display(), x));
+            }
+        }
+    }
+    fn show_integrity_dialog(&mut self, ctx: &egui::Context) {
+        let mut repair = false;
+        let mut dismissed = false;
+        if let Some(report) = &self.integrity_report {
+            egui::Window::new("Document check")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    if report.problems.is_empty() {
+                        ui.label("No problems were found");
+                    } else {
+                        if let Some((path, _)) = &report.unopened {
+                            ui.label(format!(
+                                "'{}' can't be opened until it is repaired",
+                                path.display()
+                            ));
+                        }
+                        egui::ScrollArea::vertical()
+                            .max_height(300.0)
+                            .show(ui, |ui| {
+                                for problem in report.problems.iter() {
+                                    ui.label(format!("• {}", problem));
+                                }
+                            });
+                    }
+                    ui.horizontal(|ui| {
+                        if !report.problems.is_empty() {
+                            repair = ui
+                                .button(if report.unopened.is_some() {
+                                    "Repair and open"
+                                } else {
+                                    "Repair"
+                                })
+                                .clicked();
+                        }
+                        dismissed = ui
+                            .button(if report.problems.is_empty() {
+                                "Ok"
+                            } else {
+                                "Cancel"
+                            })
+                            .clicked();
+                    });
+                });
+        }
+        if repair {
+            let report = self.integrity_report.take().unwrap();
+            if let Some((path, mut document)) = report.unopened {
+                document.repair();
+                self.replace_document(&path, *document);
+            } else {
+                let undo = {
+                    let mut document = self.document.write();
+                    document.begin_transaction();
+                    document.repair();
+                    document.commit_transaction()
+                };
+                if let Some(undo) = undo {
+                    self.record_undo(undo);
+                }
+            }
+            self.modified_since_last_saved = true;
+            self.layout_cache_needs_updating = true;
+        } else if dismissed {
+            self.integrity_report = None;
+        }
+    }
+    fn show_error_dialog(&mut self, ctx: &egui::Context) {
+        let mut dismissed = false;
+        if let Some(message) = &self.error_message {
+            egui::Window::new("Something went wrong")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(message);
+                    dismissed = ui.button("Ok").clicked();
+                });
+        }
+        if dismissed {
+            self.error_message = None;
+        }
     }
     fn write_dot(&self) {
         let filename = rfd::FileDialog::new()
@@ -727,13 +1439,27 @@ impl KanbanRS {
             writeln!(&mut file, "}}").unwrap();
         }
     }
-    pub fn save_file(&mut self, force_choose_file: bool) {
+    /// Ask where to write an export, and write it there.
+    fn write_export(&mut self, filter_name: &str, extension: &str, contents: &str) {
+        let Some(filename) = rfd::FileDialog::new()
+            .add_filter(filter_name, &[extension])
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(x) = fs::write(&filename, contents) {
+            self.error_message = Some(format!("Could not write '{}'\n\n{}", filename.display(), x));
+        }
+    }
+    /// Save the document, asking for a filename if there isn't one yet or if `force_choose_file`
+    /// is set. Returns true if the document was saved.
+    pub fn save_file(&mut self, force_choose_file: bool) -> bool {
         if self.save_file_name.is_none() || force_choose_file {
             let filename = rfd::FileDialog::new()
                 .add_filter("Kanban", &["kan"])
                 .save_file();
             if filename.is_none() {
-                return;
+                return false;
             }
             self.save_file_name = filename;
         }
@@ -741,24 +1467,133 @@ impl KanbanRS {
         // field while trying to write to it, instead of the source object.
         //
         // This should prevent that
-        let mut tmp_path = self.save_file_name.clone().unwrap();
-        tmp_path.set_extension("kan.bak");
-        let file = fs::File::create(&tmp_path);
-        if let Err(x) =
-            serde_json::to_writer(file.unwrap(), &self.document.try_read().unwrap().clone())
-        {
-            println!("Error on saving: {}", x);
-        }
-        if let Err(x) = fs::rename(&tmp_path, self.save_file_name.as_ref().unwrap()) {
-            println!("Error! {}", x);
+        let document = self.document.try_read().unwrap().clone();
+        let path = self.save_file_name.clone().unwrap();
+        if let Err(x) = kanban::persistence::save(&path, &document) {
+            self.error_message = Some(format!("Could not save '{}'\n\n{}", path.display(), x));
+            return false;
         }
         self.modified_since_last_saved = false;
+        // The recovery files belong with the path the document was opened from, which is
+        // different after "Save As"
+        if let Some(recovery) = self.recovery.take() {
+            recovery.discard();
+        }
+        self.recovery = Some(Recovery::new(&path));
         self.write_recents();
+        true
     }
 
     fn undo(&mut self) {
-        if let Some(item) = self.undo_buffer.pop_back() {
-            item.undo(&mut self.document.write());
+        let touched = self
+            .undo_history
+            .undo(&mut self.document.write())
+            .map(|x| x.touched());
+        self.after_history_change(touched.unwrap_or_default());
+    }
+    fn redo(&mut self) {
+        let touched = self
+            .undo_history
+            .redo(&mut self.document.write())
+            .map(|x| x.touched());
+        self.after_history_change(touched.unwrap_or_default());
+    }
+    fn after_history_change(&mut self, touched: Vec<Target>) {
+        if touched.is_empty() {
+            return;
         }
+        self.journal(&touched);
+        self.modified_since_last_saved = true;
+        self.layout_cache_needs_updating = true;
+    }
+    fn show_history(&mut self, ctx: &egui::Context) {
+        let mut rewind_to = None;
+        egui::Window::new("History")
+            .open(&mut self.history_open)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // Position 0 is before any of the changes
+                    if ui
+                        .selectable_label(!self.undo_history.can_undo(), "Start")
+                        .clicked()
+                    {
+                        rewind_to = Some(0);
+                    }
+                    let past = self.undo_history.past().count();
+                    let entries = self
+                        .undo_history
+                        .past()
+                        .map(|x| (x, false))
+                        .chain(self.undo_history.future().map(|x| (x, true)));
+                    for (position, (entry, undone)) in (1..).zip(entries) {
+                        let local: DateTime<Local> = entry.time.into();
+                        let mut text = RichText::new(format!(
+                            "{} {} {}",
+                            local.format("%I:%M:%S %P"),
+                            entry.description,
+                            entry.item.subject()
+                        ));
+                        if undone {
+                            text = text.weak();
+                        }
+                        if ui.selectable_label(position == past, text).clicked() {
+                            rewind_to = Some(position);
+                        }
+                    }
+                });
+            });
+        if let Some(position) = rewind_to {
+            let touched = self
+                .undo_history
+                .rewind_to(&mut self.document.write(), position);
+            self.after_history_change(touched);
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kanban::recurrence::Recurrence;
+    #[test]
+    fn test_complete_recurring_in_editor() {
+        let mut app = KanbanRS::new();
+        let mut chore = app.document.write().get_new_task();
+        chore.recurrence = Some(Recurrence::Daily);
+        app.document.write().replace_task(&chore);
+        let editor = Arc::new(RwLock::new(kanban::editor::state_from(
+            &chore,
+            app.editor_tx.clone(),
+        )));
+        app.open_editors.push(editor.clone());
+
+        editor.write().item_copy.completed = Some(Utc::now());
+        let mut request = EditorRequest::UpdateItem(editor.read().item_copy.clone());
+        app.handle_editor_request(&mut request);
+        let next = app.document.read().get_task(1).cloned().unwrap();
+        assert_eq!(next.recurrence, Some(Recurrence::Daily));
+        assert!(editor.read().item_copy.recurrence.is_none());
+
+        // Applying again, as accepting the changes does, leaves the rule with the next one
+        let mut request = EditorRequest::UpdateItem(editor.read().item_copy.clone());
+        app.handle_editor_request(&mut request);
+        assert!(app
+            .document
+            .read()
+            .get_task(0)
+            .unwrap()
+            .recurrence
+            .is_none());
+        assert!(app.document.read().get_task(2).is_none());
+        assert!(app.modified_since_last_saved);
+    }
+    #[test]
+    fn test_completing_marks_modified() {
+        let mut app = KanbanRS::new();
+        let mut chore = app.document.write().get_new_task();
+        chore.recurrence = Some(Recurrence::Daily);
+        app.document.write().replace_task(&chore);
+        app.handle_summary_action(&SummaryAction::MarkCompleted(chore.id));
+        assert!(app.document.read().get_task(1).is_some());
+        assert!(app.modified_since_last_saved);
     }
 }